@@ -1,31 +1,47 @@
 use crate::{
+    block::Block,
+    buffer::{Scroll, SelectMode, WindowLine},
+    buffer_list, diff,
+    file::DiskChange,
+    highlight::Highlighting,
+    layout::{self, Edge, WindowLayout},
+    options::Options,
+    swap,
+    syntax_tree::TextObject,
+    tab::{self, Tab},
     token::{
         display_token::{DisplayToken, WindowChange},
         get_token_from_chars, AppendToken, CommandToken, InsertToken, MotionToken, NormalToken,
-        OperatorToken, Token,
+        OperatorToken, Token, VisualToken,
     },
     ui::Term,
     Buffer, Ui, Window,
 };
 
-use crate::{add_safe, sub_safe};
+use crate::sub_safe;
 use anyhow::Result as AnyHowResult;
 use crossterm::{
-    event::EnableMouseCapture,
+    event::{
+        DisableMouseCapture, EnableFocusChange, EnableMouseCapture, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     execute, terminal,
     terminal::{enable_raw_mode, ClearType},
 };
-use id_tree::{InsertBehavior::*, Node, NodeId, RemoveBehavior::*, Tree};
 use log::trace;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Direction, Rect},
     Terminal,
 };
+use ropey::Rope;
 use std::collections::HashMap;
 use std::io::stdout;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+pub const NO_WRITE_ERR: &str = "E37: No write since last change (add ! to override)";
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum Mode {
     Insert,
@@ -33,6 +49,7 @@ pub enum Mode {
     Visual,
     Normal,
     Command,
+    Confirm,
 }
 
 impl Default for Mode {
@@ -41,17 +58,99 @@ impl Default for Mode {
     }
 }
 
+impl Mode {
+    /// The name the footer shows, which for visual mode tells the kind of selection.
+    pub fn label(&self, select_mode: SelectMode) -> String {
+        match (self, select_mode) {
+            (Mode::Visual, SelectMode::Char) => "Visual".to_string(),
+            (Mode::Visual, SelectMode::Line) => "Visual Line".to_string(),
+            (Mode::Visual, SelectMode::Block) => "Visual Block".to_string(),
+            (mode, _) => format!("{:?}", mode),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum ConfirmAction {
+    QuitAll,
+    DeleteSwap(Uuid),
+    FileChanged(Uuid),
+    Overwrite(Vec<Uuid>, CommandToken),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum HistoryKind {
+    Command,
+    Search,
+}
+
+/// What the left mouse button is dragging while it is held.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MouseDrag {
+    /// The cursor, selecting from where the button went down.
+    Select,
+    /// The border below or right of a window, which changes that window's size.
+    Border(Uuid, Direction),
+}
+
+/// A blockwise `I`, `A` or `c` in progress: what gets typed from `start` on the block's first
+/// line goes in on lines `first..=last` too when insert mode ends, at `column` or at the end
+/// of each line without one. `pad` fills out lines that end short of the column.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BlockInsert {
+    pub start: usize,
+    pub first: usize,
+    pub last: usize,
+    pub column: Option<usize>,
+    pub pad: bool,
+    pub undo_depth: usize,
+}
+
+/// Tab completion of a command's argument; repeated tabs cycle through `matches`.
+#[derive(Clone, Debug)]
+pub struct Completion {
+    pub prefix: String,
+    pub matches: Vec<String>,
+    pub index: usize,
+}
+
+impl Completion {
+    fn current(&self) -> String {
+        format!("{}{}", self.prefix, self.matches[self.index])
+    }
+}
+
 pub struct App {
     pub terminal: Term,
     pub command_text: Option<String>,
     pub buffers: HashMap<Uuid, Buffer>,
     pub ui: Ui,
     pub windows: HashMap<Uuid, Window>,
-    pub window_layout: Tree<(Rect, Uuid)>,
+    pub window_layout: WindowLayout,
     pub current_window_id: Uuid,
     pub current_buffer_id: Uuid,
     pub should_quit: bool,
     pub mode: Mode,
+    pub command_history: Vec<String>,
+    pub search_history: Vec<String>,
+    /// Whether the command line holds a `:` command or a `/` search.
+    pub command_line_kind: HistoryKind,
+    pub command_line_window: Option<(Uuid, HistoryKind)>,
+    pub completion: Option<Completion>,
+    pub pending_confirm: Option<ConfirmAction>,
+    pub options: Options,
+    pub highlighting: Highlighting,
+    pub last_swap_write: Instant,
+    pub last_disk_check: Instant,
+    pub alternate_buffer_id: Option<Uuid>,
+    pub last_buffer_number: usize,
+    pub buffer_numbers: HashMap<String, usize>,
+    pub previous_window_id: Option<Uuid>,
+    pub tabs: Vec<Tab>,
+    pub current_tab: usize,
+    pub mouse_drag: Option<MouseDrag>,
+    pub block_register: Option<Vec<String>>,
+    pub block_insert: Option<BlockInsert>,
 }
 
 impl App {
@@ -59,9 +158,13 @@ impl App {
         self.buffers.get_mut(&self.current_buffer_id)
     }
 
+    /// Numbers windows in layout order, left to right and top to bottom, which is the order
+    /// `Ctrl-w w` cycles through and `z<N>` selects from.
     pub fn reorder_windows(&mut self) {
-        for (idx, window) in self.windows.values_mut().enumerate() {
-            window.order = idx
+        for (idx, window_id) in self.window_layout.windows().into_iter().enumerate() {
+            if let Some(window) = self.windows.get_mut(&window_id) {
+                window.order = idx;
+            }
         }
     }
 
@@ -85,17 +188,75 @@ impl App {
     }
 
     pub fn render_ui(&mut self) {
+        for window in self.windows.values_mut() {
+            if let Some(buffer) = self.buffers.get_mut(&window.buffer_id) {
+                window.modified = buffer.is_modified();
+                window.cursor_line = self.options.cursorline;
+                window.tabstop = buffer.local_options.tabstop;
+                if window.id == self.current_window_id {
+                    if !window.large_file {
+                        buffer.update_folds();
+                    }
+                    buffer.page_size = window.text_height();
+                    buffer.scroll_to_cursor(self.options.scrolloff);
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    window.update(change);
+                    let selection = match (&self.mode, buffer.select_mode) {
+                        (Mode::Visual, SelectMode::Block) => buffer
+                            .selected_block()
+                            .map(|block| buffer.block_ranges(&block))
+                            .unwrap_or_default(),
+                        (Mode::Visual, _) => buffer.get_selected_range().into_iter().collect(),
+                        _ => vec![],
+                    };
+                    window.sync_selection(&buffer.text, &selection);
+                } else {
+                    window.clamp_view(&buffer.text);
+                    window.title = Some(buffer.title.clone());
+                    window.selection.clear();
+                }
+                if window.large_file {
+                    window.cache_viewport(&buffer.text);
+                } else {
+                    if window.line_num_cache.len() != sub_safe(buffer.text.len_lines(), 1) {
+                        window.cache_line_numbers(&buffer.text);
+                    }
+                    window.sync_folds(&buffer.folds, &buffer.text, self.options.foldcolumn);
+                    window.highlight_viewport(
+                        &buffer.text,
+                        buffer.changed_tick,
+                        buffer.syntax_tree.as_ref(),
+                    );
+                }
+            }
+        }
         self.ui.draw_view_port(
             &self.current_window_id,
-            &self.mode,
+            &self.mode.label(
+                self.get_buffer()
+                    .map(|b| b.select_mode)
+                    .unwrap_or(SelectMode::Char),
+            ),
             self.get_buffer().map(|b| (b.x_pos, b.y_pos)),
+            self.get_buffer().map(|b| b.is_modified()).unwrap_or(false),
+            self.get_buffer().map(|b| b.file_info()),
             &self.command_text,
+            self.tab_labels(),
             self.windows.values().collect::<Vec<&Window>>(),
             &mut self.terminal,
         )
     }
 
     pub fn set_command_mode(&mut self) {
+        self.command_line_kind = HistoryKind::Command;
         self.mode = Mode::Command
     }
 
@@ -103,13 +264,27 @@ impl App {
         self.mode = Mode::Insert
     }
 
-    #[allow(dead_code)]
-    pub fn set_visual_mode(&mut self) {
+    pub fn set_visual_mode(&mut self, select_mode: SelectMode) {
         self.mode = Mode::Visual;
-        self.get_mut_buffer().map(|b| {
-            let idx = b.get_cursor_idx();
-            b.start_select_pos = Some(idx);
-        });
+        if let Some(buffer) = self.get_mut_buffer() {
+            let idx = buffer.get_cursor_idx();
+            buffer.start_select_pos = Some(idx);
+            buffer.select_mode = select_mode;
+            buffer.block_to_eol = false;
+        }
+    }
+
+    /// `v`, `V` or `Ctrl-v` in visual mode: the key for the kind of selection there is
+    /// leaves visual mode, the others switch to their kind.
+    pub fn switch_visual_mode(&mut self, select_mode: SelectMode) {
+        if self.get_buffer().map(|b| b.select_mode) == Some(select_mode) {
+            if let Some(buffer) = self.get_mut_buffer() {
+                buffer.end_selection();
+            }
+            self.set_normal_mode();
+        } else if let Some(buffer) = self.get_mut_buffer() {
+            buffer.select_mode = select_mode;
+        }
     }
 
     pub fn set_append_mode(&mut self) {
@@ -125,14 +300,15 @@ impl App {
         enable_raw_mode()?;
         let _ = execute!(stdout(), terminal::Clear(ClearType::All));
         let mut stdout = stdout();
-        execute!(stdout, EnableMouseCapture)?;
+        execute!(stdout, EnableMouseCapture, EnableFocusChange)?;
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
-        let mut window_layout: Tree<(Rect, Uuid)> = Tree::new();
-        let mut buffers = HashMap::new();
         let mut windows = HashMap::new();
-        let ui = Ui::new(&mut terminal);
-        let buffer = Buffer::new(file_name.clone())?;
+        let mut ui = Ui::new(&mut terminal);
+        let options = Options::default();
+        let highlighting = Highlighting::load();
+        let buffer = Buffer::open(file_name.clone(), options.large_file_bytes())?;
+        ui.theme = highlighting.ui_theme.clone();
         let mut window = Window::new(&WindowChange {
             id: buffer.id,
             x_pos: buffer.x_pos,
@@ -143,18 +319,18 @@ impl App {
             current_page: buffer.current_page,
             ..WindowChange::default()
         });
-        let _root_node = window_layout.insert(Node::new((ui.text_area, window.id)), AsRoot)?;
+        let window_layout = WindowLayout::new(window.id);
         let current_buffer_id = buffer.id.clone();
         let current_window_id = window.id.clone();
-        window.set_highlight();
+        window.large_file = buffer.large_file;
+        window.set_highlight(&highlighting, &buffer);
         window.cache_window_content(&buffer.text);
-        buffers.insert(buffer.id, buffer);
         windows.insert(current_window_id, window);
 
-        Ok(Self {
+        let mut app = Self {
             terminal,
             windows,
-            buffers,
+            buffers: HashMap::new(),
             ui,
             window_layout,
             should_quit: false,
@@ -162,7 +338,29 @@ impl App {
             current_window_id,
             mode: Mode::Normal,
             command_text: None,
-        })
+            command_history: vec![],
+            search_history: vec![],
+            command_line_kind: HistoryKind::Command,
+            command_line_window: None,
+            completion: None,
+            pending_confirm: None,
+            options,
+            highlighting,
+            last_swap_write: Instant::now(),
+            last_disk_check: Instant::now(),
+            alternate_buffer_id: None,
+            last_buffer_number: 0,
+            buffer_numbers: HashMap::new(),
+            previous_window_id: None,
+            tabs: vec![],
+            current_tab: 0,
+            mouse_drag: None,
+            block_register: None,
+            block_insert: None,
+        };
+        app.add_buffer(buffer);
+        app.report_swap_conflict(current_buffer_id);
+        Ok(app)
     }
 
     pub fn new_split(
@@ -170,49 +368,285 @@ impl App {
         file_name: Option<String>,
         direction: Direction,
     ) -> AnyHowResult<()> {
-        if let Some(current_window) = self.get_window().clone() {
-            if let Ok(Some(current_node_id)) = self.get_current_node_id() {
-                let buffer = Buffer::new(file_name.clone())?;
+        match file_name.filter(|f| !f.trim().is_empty()) {
+            Some(file_name) => match self.find_by_path(&file_name) {
+                Some(buffer_id) => self.split_window(buffer_id, direction)?,
+                None => {
+                    let buffer = Buffer::open(Some(file_name), self.options.large_file_bytes())?;
+                    self.split_with_buffer(buffer, direction)?;
+                    self.report_swap_conflict(self.current_buffer_id);
+                }
+            },
+            None => self.split_window(self.current_buffer_id, direction)?,
+        }
+        Ok(())
+    }
 
-                if let [split1, split2, ..] = self.ui.split_ui(&current_window, direction)[..] {
-                    let mut window = Window::new(&WindowChange {
-                        id: buffer.id,
-                        area: Some(split2),
-                        x_pos: buffer.x_pos,
-                        y_pos: buffer.y_pos,
-                        title: Some(buffer.title.clone()),
-                        page_size: buffer.page_size,
-                        current_page: buffer.current_page,
-                        ..WindowChange::default()
-                    });
-                    let _ = self.window_layout.insert(
-                        Node::new((split1, current_window.id)),
-                        UnderNode(&current_node_id),
-                    )?;
-                    let _ = self
-                        .window_layout
-                        .insert(Node::new((split2, window.id)), UnderNode(&current_node_id))?;
-                    let current_buffer_id = buffer.id.clone();
-                    let current_window_id = window.id.clone();
-                    window.set_highlight();
-                    window.cache_window_content(&buffer.text);
-                    self.buffers.insert(current_buffer_id, buffer);
-                    self.windows.insert(current_window_id, window);
-                    let _ = self
-                        .window_layout
-                        .get_mut(&current_node_id)
-                        .map(|n| (n.data().0, Uuid::new_v4()));
-                    self.get_mut_window().map(|w| {
-                        w.area = Some(split1);
-                        w.x_offset = add_safe(split1.x, 4);
-                        w.y_offset = add_safe(split1.y, 1);
-                    });
-                    self.current_buffer_id = current_buffer_id;
-                    self.current_window_id = current_window_id;
-                    self.reorder_windows();
+    pub fn split_with_buffer(&mut self, buffer: Buffer, direction: Direction) -> AnyHowResult<()> {
+        let buffer_id = self.add_buffer(buffer);
+        let result = self.split_window(buffer_id, direction);
+        if result.is_err() {
+            self.drop_unshown_scratch(buffer_id);
+        }
+        result
+    }
+
+    /// Adds `buffer` to the buffer list. A file gets back the number it had before `:bd`, other
+    /// buffers the next unused one.
+    pub fn add_buffer(&mut self, mut buffer: Buffer) -> Uuid {
+        let remembered = buffer
+            .file_path
+            .as_ref()
+            .and_then(|path| self.buffer_numbers.get(path))
+            .copied();
+        buffer.number = remembered.unwrap_or_else(|| {
+            self.last_buffer_number += 1;
+            self.last_buffer_number
+        });
+        if let Some(path) = &buffer.file_path {
+            self.buffer_numbers.insert(path.clone(), buffer.number);
+        }
+        let buffer_id = buffer.id;
+        self.buffers.insert(buffer_id, buffer);
+        buffer_id
+    }
+
+    fn find_by_path(&self, file_name: &str) -> Option<Uuid> {
+        self.buffers
+            .values()
+            .find(|b| b.file_path.as_deref() == Some(file_name.trim()))
+            .map(|b| b.id)
+    }
+
+    /// Splits the current window and focuses a new window onto `buffer_id`, starting from the
+    /// cursor the buffer last had.
+    pub fn split_window(&mut self, buffer_id: Uuid, direction: Direction) -> AnyHowResult<()> {
+        if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+            window.save_view(buffer);
+        }
+        if let (Some(current_window), Some(buffer)) =
+            (self.get_window().cloned(), self.buffers.get(&buffer_id))
+        {
+            let window = Window::new(&WindowChange {
+                id: buffer.id,
+                area: current_window.area,
+                x_pos: buffer.x_pos,
+                y_pos: buffer.y_pos,
+                title: Some(buffer.title.clone()),
+                page_size: buffer.page_size,
+                current_page: buffer.current_page,
+            });
+            let window_id = window.id;
+            self.windows.insert(window_id, window);
+            if !self
+                .window_layout
+                .split(current_window.id, window_id, direction)
+            {
+                self.windows.remove(&window_id);
+                return Err(anyhow::Error::msg("E36: Not enough room"));
+            }
+            self.apply_layout();
+            if let (Some(window), Some(buffer)) = (
+                self.windows.get_mut(&window_id),
+                self.buffers.get(&buffer_id),
+            ) {
+                window.large_file = buffer.large_file;
+                window.set_highlight(&self.highlighting, buffer);
+                window.cache_window_content(&buffer.text);
+            }
+            self.current_buffer_id = buffer_id;
+            self.previous_window_id = Some(self.current_window_id);
+            self.current_window_id = window_id;
+        }
+        Ok(())
+    }
+
+    /// Makes `window_id` the current window. The buffer's cursor is the current window's view,
+    /// so the view of the window being left is saved and the new one's loaded in its place.
+    pub fn focus_window(&mut self, window_id: Uuid) {
+        if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+            window.save_view(buffer);
+        }
+        if let Some(buffer_id) = self.windows.get(&window_id).map(|w| w.buffer_id) {
+            if window_id != self.current_window_id {
+                self.previous_window_id = Some(self.current_window_id);
+            }
+            self.current_window_id = window_id;
+            self.current_buffer_id = buffer_id;
+            if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+                window.load_view(buffer);
+            }
+        }
+    }
+
+    /// Shows `buffer_id` in the current window at the cursor the buffer last had.
+    pub fn show_buffer(&mut self, buffer_id: Uuid) {
+        if !self.buffers.contains_key(&buffer_id) {
+            return;
+        }
+        if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+            window.save_view(buffer);
+        }
+        if let Some(window) = self.get_mut_window() {
+            window.buffer_id = buffer_id;
+        }
+        if self.current_buffer_id != buffer_id {
+            self.alternate_buffer_id = Some(self.current_buffer_id);
+        }
+        self.current_buffer_id = buffer_id;
+        self.refresh_current_window();
+    }
+
+    /// Every window, including those on tabs in the background.
+    fn all_windows(&self) -> impl Iterator<Item = &Window> {
+        self.windows
+            .values()
+            .chain(self.tabs.iter().flat_map(|t| t.windows.values()))
+    }
+
+    fn is_displayed(&self, buffer_id: Uuid) -> bool {
+        self.all_windows().any(|w| w.buffer_id == buffer_id)
+    }
+
+    /// Whether the current window may stop showing its buffer: it is unmodified, shown in
+    /// another window too, or `hidden` lets it stay loaded with unsaved changes.
+    fn can_abandon(&self) -> bool {
+        self.options.hidden
+            || !self.get_buffer().map(|b| b.is_modified()).unwrap_or(false)
+            || self
+                .all_windows()
+                .filter(|w| w.buffer_id == self.current_buffer_id)
+                .count()
+                > 1
+    }
+
+    pub fn switch_buffer(&mut self, buffer_id: Uuid) -> AnyHowResult<()> {
+        if buffer_id == self.current_buffer_id {
+            return Ok(());
+        }
+        if !self.can_abandon() {
+            return Err(anyhow::Error::msg(NO_WRITE_ERR));
+        }
+        self.show_buffer(buffer_id);
+        Ok(())
+    }
+
+    pub fn switch_to_alternate(&mut self) -> AnyHowResult<()> {
+        match self
+            .alternate_buffer_id
+            .filter(|id| self.buffers.contains_key(id))
+        {
+            Some(buffer_id) => self.switch_buffer(buffer_id),
+            None => Err(anyhow::Error::msg(buffer_list::NO_ALTERNATE_ERR)),
+        }
+    }
+
+    /// The `:ls` listing, one buffer per line.
+    pub fn list_buffers(&self) -> String {
+        buffer_list::by_number(&self.buffers)
+            .into_iter()
+            .map(|b| {
+                buffer_list::listing(
+                    b,
+                    b.id == self.current_buffer_id,
+                    Some(b.id) == self.alternate_buffer_id,
+                    self.is_displayed(b.id),
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Shows the `:ls` listing in a scratch window, the way `q:` shows the command history.
+    /// A listing window already open on this tab is refreshed and focused instead.
+    pub fn open_buffer_list(&mut self) -> AnyHowResult<()> {
+        let listing = self.list_buffers();
+        let shown = self.windows.values().find_map(|w| {
+            self.buffers
+                .get(&w.buffer_id)
+                .filter(|b| b.scratch && b.title == buffer_list::LIST_TITLE)
+                .map(|b| (w.id, b.id))
+        });
+        match shown {
+            Some((window_id, buffer_id)) => {
+                self.focus_window(window_id);
+                if let Some(buffer) = self.buffers.get_mut(&buffer_id) {
+                    buffer.text = Rope::from_str(&listing);
+                    buffer.x_pos = 0;
+                    buffer.y_pos = 0;
+                    buffer.current_page = 0;
                 }
+                self.refresh_current_window();
+                Ok(())
+            }
+            None => {
+                let buffer = Buffer::new_scratch(buffer_list::LIST_TITLE, &listing)?;
+                self.split_with_buffer(buffer, Direction::Vertical)
             }
         }
+    }
+
+    /// `:bd` and `:bw`. Windows showing the buffer switch to the alternate or next buffer, or
+    /// to a new empty one when it was the last. Wiping also forgets the file's buffer number.
+    pub fn delete_buffer(
+        &mut self,
+        arg: Option<String>,
+        force: bool,
+        wipe: bool,
+    ) -> AnyHowResult<()> {
+        let buffer_id = match arg {
+            Some(arg) => buffer_list::find(&self.buffers, &arg)?,
+            None => self.current_buffer_id,
+        };
+        if let Some(buffer) = self.buffers.get(&buffer_id) {
+            if buffer.is_modified() && !force {
+                return Err(anyhow::Error::msg(format!(
+                    "E89: No write since last change for buffer {} (add ! to override)",
+                    buffer.number
+                )));
+            }
+        }
+        let replacement = self
+            .alternate_buffer_id
+            .filter(|id| *id != buffer_id && self.buffers.contains_key(id))
+            .or_else(|| {
+                buffer_list::cycle(&self.buffers, buffer_id, true).filter(|id| *id != buffer_id)
+            });
+        let replacement = match replacement {
+            Some(replacement) => replacement,
+            None => self.add_buffer(Buffer::open(None, self.options.large_file_bytes())?),
+        };
+        if self.current_buffer_id == buffer_id {
+            self.show_buffer(replacement);
+        }
+        if let Some(buffer) = self.buffers.get(&replacement) {
+            for window in self
+                .windows
+                .values_mut()
+                .chain(self.tabs.iter_mut().flat_map(|t| t.windows.values_mut()))
+                .filter(|w| w.buffer_id == buffer_id)
+            {
+                window.buffer_id = replacement;
+                window.save_view(buffer);
+                window.large_file = buffer.large_file;
+                window.set_highlight(&self.highlighting, buffer);
+                window.cache_window_content(&buffer.text);
+            }
+        }
+        if let Some(mut buffer) = self.buffers.remove(&buffer_id) {
+            buffer.remove_swap();
+            if wipe {
+                buffer
+                    .file_path
+                    .map(|path| self.buffer_numbers.remove(&path));
+            }
+        }
+        if self.alternate_buffer_id == Some(buffer_id) {
+            self.alternate_buffer_id = None;
+        }
+        if self.command_line_window.map(|(id, _)| id) == Some(buffer_id) {
+            self.command_line_window = None;
+        }
         Ok(())
     }
 
@@ -230,7 +664,7 @@ impl App {
                         current_page: buffer.current_page,
                         ..WindowChange::default()
                     };
-                    window.cache_current_line(&buffer.text, buffer.y_pos.clone() as usize);
+                    window.cache_current_line(&buffer.text, buffer.y_pos);
                     window.update(change);
                 }
             }
@@ -246,13 +680,17 @@ impl App {
                         current_page: buffer.current_page,
                         ..WindowChange::default()
                     };
-                    window.cache_current_line(&buffer.text, buffer.y_pos as usize);
+                    window.cache_current_line(&buffer.text, buffer.y_pos);
                     window.update(change)
                 }
             }
             InsertToken::Esc => {
                 self.set_normal_mode();
                 self.get_mut_buffer().map(|b| b.start_select_pos = None);
+                if let Some(block_insert) = self.block_insert.take() {
+                    self.finish_block_insert(block_insert);
+                    self.render_ui();
+                }
             }
             InsertToken::Enter => {
                 if let (Some(window), Some(buffer)) = self.get_mut_pair() {
@@ -266,97 +704,958 @@ impl App {
                         current_page: buffer.current_page,
                         ..WindowChange::default()
                     };
-                    window.cache_new_line(&buffer.text, buffer.y_pos as usize);
+                    window.cache_new_line(&buffer.text, buffer.y_pos);
+                    buffer.folds.insert_line(buffer.y_pos);
                     window.cache_line_numbers(&buffer.text);
                     window.update(change)
                 }
-                self.render_ui();
+                self.render_ui();
+            }
+        }
+    }
+
+    /// Gives each window its area in the layout.
+    fn apply_layout(&mut self) {
+        for (window_id, rect) in self.window_layout.areas(self.ui.text_area) {
+            if let Some(window) = self.windows.get_mut(&window_id) {
+                window.set_area(rect);
+            }
+        }
+        self.reorder_windows();
+    }
+
+    /// `Ctrl-w h/j/k/l`: focuses the window beside the current one.
+    fn focus_neighbour(&mut self, edge: Edge) {
+        let areas = self
+            .windows
+            .values()
+            .filter_map(|w| w.area.map(|area| (w.id, area)))
+            .collect::<Vec<(Uuid, Rect)>>();
+        let neighbour = self.get_window().and_then(|w| {
+            layout::neighbour(&areas, &w.area?, (w.cursor_x_pos(), w.cursor_y_pos()), edge)
+        });
+        if let Some(window_id) = neighbour {
+            self.focus_window(window_id);
+        }
+    }
+
+    /// `Ctrl-w w/W`: focuses the next or previous window in layout order, wrapping around.
+    fn cycle_window(&mut self, forward: bool) {
+        let windows = self.window_layout.windows();
+        if let Some(idx) = windows.iter().position(|id| *id == self.current_window_id) {
+            let next = if forward {
+                (idx + 1) % windows.len()
+            } else {
+                (idx + windows.len() - 1) % windows.len()
+            };
+            self.focus_window(windows[next]);
+        }
+    }
+
+    /// `Ctrl-w x`: swaps the current window with the next one beside it, or the previous one
+    /// when it is last. The cursor stays where it was on screen, in the other window.
+    fn exchange_window(&mut self) {
+        if let Some(other) = self.window_layout.exchange(self.current_window_id) {
+            self.apply_layout();
+            self.focus_window(other);
+        }
+    }
+
+    /// `Ctrl-w r/R`: rotates the windows beside the current one, which moves along with them.
+    fn rotate_windows(&mut self, downwards: bool) -> AnyHowResult<()> {
+        self.window_layout
+            .rotate(self.current_window_id, downwards)?;
+        self.apply_layout();
+        Ok(())
+    }
+
+    /// Resizes the current window's height (`Vertical`) or width (`Horizontal`) to whatever
+    /// `size` makes of its current one. The nearest enclosing split along that direction
+    /// changes, so the windows beside it give up or take the difference.
+    fn resize_window(&mut self, direction: Direction, size: impl Fn(u16) -> u16) {
+        self.window_layout
+            .resize(self.ui.text_area, self.current_window_id, direction, &size);
+        self.apply_layout();
+    }
+
+    /// `:resize` and `:vertical resize`.
+    fn resize_command(&mut self, direction: Direction, arg: &str) {
+        let current = self
+            .get_window()
+            .and_then(|w| w.area)
+            .map(|area| layout::size_along(&area, direction))
+            .unwrap_or_default();
+        let result =
+            layout::resize_target(arg, current).map(|size| self.resize_window(direction, |_| size));
+        self.set_normal_mode();
+        if let Err(e) = result {
+            self.command_text = Some(e.to_string());
+        }
+        self.render_ui();
+    }
+
+    /// `:only` and `Ctrl-w o`. Windows on a modified buffer nothing else shows stay open
+    /// unless `hidden` is set or `force` is given.
+    fn close_other_windows(&mut self, force: bool) -> AnyHowResult<()> {
+        let keep = self
+            .windows
+            .values()
+            .filter(|w| {
+                !force
+                    && !self.options.hidden
+                    && w.buffer_id != self.current_buffer_id
+                    && self
+                        .buffers
+                        .get(&w.buffer_id)
+                        .map(|b| b.is_modified())
+                        .unwrap_or(false)
+            })
+            .map(|w| w.id)
+            .collect::<Vec<Uuid>>();
+        for window_id in self.window_layout.windows() {
+            if window_id != self.current_window_id && !keep.contains(&window_id) {
+                self.window_layout.remove(window_id);
+                if let Some(window) = self.windows.remove(&window_id) {
+                    self.drop_unshown_scratch(window.buffer_id);
+                }
+            }
+        }
+        self.apply_layout();
+        if keep.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg("E445: Other window contains changes"))
+        }
+    }
+
+    /// The tab line entries, in order, each with whether it is the current tab. Empty while
+    /// there is only one tab, which leaves the tab line hidden.
+    fn tab_labels(&self) -> Vec<(String, bool)> {
+        if self.tabs.is_empty() {
+            return vec![];
+        }
+        let label = |window: Option<&Window>, windows: usize| {
+            let buffer = window.and_then(|w| self.buffers.get(&w.buffer_id));
+            tab::label(
+                &buffer.map(|b| b.title.clone()).unwrap_or_default(),
+                buffer.map(|b| b.is_modified()).unwrap_or(false),
+                windows,
+            )
+        };
+        let mut labels = self
+            .tabs
+            .iter()
+            .map(|t| (label(t.current(), t.windows.len()), false))
+            .collect::<Vec<(String, bool)>>();
+        labels.insert(
+            self.current_tab,
+            (label(self.get_window(), self.windows.len()), true),
+        );
+        labels
+    }
+
+    /// Packs the current tab's windows away so another tab can take their place.
+    fn stash_tab(&mut self) -> Tab {
+        if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+            window.save_view(buffer);
+        }
+        Tab {
+            current_window: self.current_window_id,
+            previous_window: self.previous_window_id,
+            windows: std::mem::take(&mut self.windows),
+            layout: std::mem::replace(
+                &mut self.window_layout,
+                WindowLayout::new(self.current_window_id),
+            ),
+        }
+    }
+
+    /// Shows `tab`'s windows. Their buffers may have changed while the tab was in the
+    /// background, so each window caches its content again.
+    fn unstash_tab(&mut self, tab: Tab) {
+        self.windows = tab.windows;
+        self.window_layout = tab.layout;
+        self.current_window_id = tab.current_window;
+        self.previous_window_id = tab.previous_window;
+        for window in self.windows.values_mut() {
+            if let Some(buffer) = self.buffers.get(&window.buffer_id) {
+                window.cache_window_content(&buffer.text);
+            }
+        }
+        if let Some(buffer_id) = self.get_window().map(|w| w.buffer_id) {
+            self.current_buffer_id = buffer_id;
+        }
+        if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+            window.load_view(buffer);
+        }
+        self.update_tab_line();
+    }
+
+    /// Shows the tab line while there is more than one tab, and lays the windows out in
+    /// whatever room it leaves.
+    fn update_tab_line(&mut self) {
+        if let Ok(size) = self.terminal.size() {
+            self.ui.resize(size, !self.tabs.is_empty());
+        }
+        self.apply_layout();
+    }
+
+    /// `gt`, `gT` and `{N}gt`: shows the tab at `idx`, counting from 0.
+    fn go_to_tab(&mut self, idx: usize) {
+        if idx == self.current_tab || idx > self.tabs.len() {
+            return;
+        }
+        let current = self.stash_tab();
+        self.tabs.insert(self.current_tab, current);
+        let tab = self.tabs.remove(idx);
+        self.current_tab = idx;
+        self.unstash_tab(tab);
+    }
+
+    /// `:tabnew [file]`: opens a tab after the current one with a single window on `file_name`,
+    /// or on a new empty buffer.
+    fn new_tab(&mut self, file_name: Option<String>) -> AnyHowResult<()> {
+        let (buffer_id, opened) = match file_name.as_ref().and_then(|f| self.find_by_path(f)) {
+            Some(buffer_id) => (buffer_id, false),
+            None => {
+                let buffer = Buffer::open(file_name, self.options.large_file_bytes())?;
+                (self.add_buffer(buffer), true)
+            }
+        };
+        let mut window = match self.buffers.get(&buffer_id) {
+            Some(buffer) => {
+                let mut window = Window::new(&WindowChange {
+                    id: buffer.id,
+                    area: Some(self.ui.text_area),
+                    x_pos: buffer.x_pos,
+                    y_pos: buffer.y_pos,
+                    title: Some(buffer.title.clone()),
+                    page_size: buffer.page_size,
+                    current_page: buffer.current_page,
+                });
+                window.large_file = buffer.large_file;
+                window.set_highlight(&self.highlighting, buffer);
+                window
+            }
+            None => return Ok(()),
+        };
+        let current = self.stash_tab();
+        self.tabs.insert(self.current_tab, current);
+        self.current_tab += 1;
+        window.save_view(&self.buffers[&buffer_id]);
+        let window_id = window.id;
+        self.unstash_tab(Tab {
+            current_window: window_id,
+            previous_window: None,
+            windows: HashMap::from([(window_id, window)]),
+            layout: WindowLayout::new(window_id),
+        });
+        if opened {
+            self.report_swap_conflict(buffer_id);
+        }
+        Ok(())
+    }
+
+    /// Whether closing the windows in `closing` leaves a modified buffer without a window,
+    /// which takes `hidden` or a `!`.
+    fn abandons_changes(&self, closing: &[Uuid]) -> bool {
+        !self.options.hidden
+            && self
+                .all_windows()
+                .filter(|w| closing.contains(&w.id))
+                .any(|w| {
+                    self.buffers
+                        .get(&w.buffer_id)
+                        .map(|b| b.is_modified())
+                        .unwrap_or(false)
+                        && !self
+                            .all_windows()
+                            .any(|o| o.buffer_id == w.buffer_id && !closing.contains(&o.id))
+                })
+    }
+
+    /// `:tabclose`: closes the current tab and shows the one after it, or the one before when
+    /// it was the last.
+    fn close_tab(&mut self, force: bool) -> AnyHowResult<()> {
+        if self.tabs.is_empty() {
+            return Err(anyhow::Error::msg("E784: Cannot close last tab page"));
+        }
+        let closing = self.windows.keys().cloned().collect::<Vec<Uuid>>();
+        if !force && self.abandons_changes(&closing) {
+            return Err(anyhow::Error::msg(NO_WRITE_ERR));
+        }
+        let closed = std::mem::take(&mut self.windows);
+        self.current_tab = self.current_tab.min(self.tabs.len() - 1);
+        let tab = self.tabs.remove(self.current_tab);
+        self.unstash_tab(tab);
+        for window in closed.values() {
+            self.drop_unshown_scratch(window.buffer_id);
+        }
+        Ok(())
+    }
+
+    /// `:tabonly`. Tabs holding the only window on a modified buffer stay open unless `hidden`
+    /// is set or `force` is given.
+    fn close_other_tabs(&mut self, force: bool) -> AnyHowResult<()> {
+        let mut kept = false;
+        let mut idx = 0;
+        while idx < self.tabs.len() {
+            let closing = self.tabs[idx]
+                .windows
+                .keys()
+                .cloned()
+                .collect::<Vec<Uuid>>();
+            if !force && self.abandons_changes(&closing) {
+                kept = true;
+                idx += 1;
+                continue;
+            }
+            let tab = self.tabs.remove(idx);
+            if idx < self.current_tab {
+                self.current_tab -= 1;
+            }
+            for window in tab.windows.values() {
+                self.drop_unshown_scratch(window.buffer_id);
+            }
+        }
+        self.update_tab_line();
+        if kept {
+            Err(anyhow::Error::msg("E445: Other window contains changes"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn drop_unshown_scratch(&mut self, buffer_id: Uuid) {
+        let scratch = self
+            .buffers
+            .get(&buffer_id)
+            .map(|b| b.scratch)
+            .unwrap_or(false);
+        if scratch && !self.is_displayed(buffer_id) {
+            if let Some(mut buffer) = self.buffers.remove(&buffer_id) {
+                buffer.remove_swap();
+            }
+            if self.command_line_window.map(|(id, _)| id) == Some(buffer_id) {
+                self.command_line_window = None;
+            }
+        }
+    }
+
+    /// Closes the current window. Its buffer stays loaded unless it is a scratch buffer no
+    /// other window shows; closing the last window closes its tab, or quits when it is the
+    /// last tab. Focus goes back to the window used before it.
+    fn on_quit(&mut self) {
+        let window_id = self.current_window_id;
+        let buffer_id = self.current_buffer_id;
+        if self
+            .command_line_window
+            .map(|(w, _)| w == buffer_id)
+            .unwrap_or(false)
+        {
+            self.command_line_window = None;
+        }
+        if self.windows.len() <= 1 && self.tabs.is_empty() {
+            return self.on_quit_all();
+        }
+        let heir = self
+            .window_layout
+            .windows()
+            .into_iter()
+            .filter(|id| *id != window_id)
+            .collect::<Vec<Uuid>>();
+        if self.windows.len() <= 1 {
+            let _ = self.close_tab(true);
+        } else if let (true, Some(first)) = (self.window_layout.remove(window_id), heir.first()) {
+            if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+                window.save_view(buffer);
+            }
+            self.windows.remove(&window_id);
+            let next = self
+                .previous_window_id
+                .filter(|id| heir.contains(id))
+                .unwrap_or(*first);
+            self.focus_window(next);
+            self.apply_layout();
+        }
+        self.drop_unshown_scratch(buffer_id);
+        self.set_normal_mode();
+        self.render_ui();
+    }
+
+    pub fn write_buffer(
+        &mut self,
+        buffer_id: Uuid,
+        file_name: Option<String>,
+        append: bool,
+    ) -> AnyHowResult<String> {
+        let buffer = self
+            .buffers
+            .get_mut(&buffer_id)
+            .ok_or_else(|| anyhow::Error::msg("E86: Buffer does not exist"))?;
+        // `:w >>` without a name appends to the buffer's own file.
+        let file_path = match file_name.filter(|f| !f.is_empty()) {
+            Some(file_name) => {
+                if buffer.file_path.is_none() {
+                    buffer.file_path = Some(file_name.clone());
+                    buffer.title = file_name.clone();
+                }
+                file_name
+            }
+            None => buffer
+                .file_path
+                .clone()
+                .ok_or_else(|| anyhow::Error::msg("E32: No file name"))?,
+        };
+        let (lines, bytes) = buffer
+            .write_to_path(&file_path, append, self.options.backup)
+            .map_err(|e| {
+                anyhow::Error::msg(format!(
+                    "\"{}\" E212: Can't open file for writing: {}",
+                    file_path, e
+                ))
+            })?;
+        if !append && buffer.file_path.as_ref() == Some(&file_path) {
+            buffer.mark_saved();
+        }
+        let verb = if append { "appended" } else { "written" };
+        Ok(format!("\"{}\" {}L, {}B {}", file_path, lines, bytes, verb))
+    }
+
+    pub fn write_all(&mut self) -> AnyHowResult<String> {
+        let ids = self
+            .buffers
+            .values()
+            .filter(|b| b.file_path.is_some())
+            .map(|b| b.id)
+            .collect::<Vec<Uuid>>();
+        let mut messages = vec![];
+        for id in ids {
+            messages.push(self.write_buffer(id, None, false)?);
+        }
+        Ok(messages.join(" "))
+    }
+
+    pub fn save_as(&mut self, file_name: String) -> AnyHowResult<String> {
+        let message = self.write_buffer(self.current_buffer_id, Some(file_name.clone()), false)?;
+        if let Some(buffer) = self.get_mut_buffer() {
+            buffer.file_path = Some(file_name.clone());
+            buffer.title = file_name;
+        }
+        self.refresh_current_window();
+        Ok(message)
+    }
+
+    pub fn edit_file(&mut self, file_name: Option<String>) -> AnyHowResult<()> {
+        match file_name {
+            Some(file_name) => {
+                let previous_id = self.current_buffer_id;
+                match self.find_by_path(&file_name) {
+                    Some(buffer_id) => self.show_buffer(buffer_id),
+                    None => {
+                        let buffer =
+                            Buffer::open(Some(file_name), self.options.large_file_bytes())?;
+                        let buffer_id = self.add_buffer(buffer);
+                        self.show_buffer(buffer_id);
+                        self.report_swap_conflict(buffer_id);
+                    }
+                }
+                let scratch = self
+                    .buffers
+                    .get(&previous_id)
+                    .map(|b| b.scratch)
+                    .unwrap_or(false);
+                if scratch && !self.is_displayed(previous_id) {
+                    if let Some(mut previous) = self.buffers.remove(&previous_id) {
+                        previous.remove_swap();
+                    }
+                }
+                return Ok(());
+            }
+            None => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    if buffer.file_path.is_none() {
+                        return Err(anyhow::Error::msg("E32: No file name"));
+                    }
+                    buffer.reload()?;
+                }
+            }
+        }
+        self.refresh_current_window();
+        Ok(())
+    }
+
+    pub fn prompt(&mut self, action: ConfirmAction, message: String) {
+        self.pending_confirm = Some(action);
+        self.mode = Mode::Confirm;
+        self.command_text = Some(message);
+    }
+
+    fn overwrite_targets(&self, token: &CommandToken) -> Vec<Uuid> {
+        let current = self.get_buffer();
+        let targets = match token {
+            CommandToken::Write | CommandToken::WriteQuit => current.into_iter().collect(),
+            CommandToken::WriteQuitIfModified => {
+                current.filter(|b| b.is_modified()).into_iter().collect()
+            }
+            CommandToken::WriteTo(file_name) | CommandToken::SaveAs(file_name) => current
+                .filter(|b| b.is_file(file_name))
+                .into_iter()
+                .collect(),
+            CommandToken::WriteAll | CommandToken::WriteQuitAll => {
+                self.buffers.values().collect::<Vec<&Buffer>>()
+            }
+            _ => vec![],
+        };
+        targets
+            .into_iter()
+            .filter(|b| b.disk_change() == DiskChange::Changed)
+            .map(|b| b.id)
+            .collect()
+    }
+
+    fn confirm_overwrite(&mut self, token: &CommandToken) -> bool {
+        let ids = self.overwrite_targets(token);
+        if ids.is_empty() {
+            return false;
+        }
+        self.prompt(
+            ConfirmAction::Overwrite(ids, token.clone()),
+            "WARNING: The file has been changed since reading it!!! Do you really want to write to it (y/n)?"
+                .to_string(),
+        );
+        true
+    }
+
+    pub fn check_external_changes(&mut self) {
+        if self.pending_confirm.is_some() {
+            return;
+        }
+        let changes = self
+            .buffers
+            .values()
+            .map(|b| (b.id, b.disk_change()))
+            .filter(|(_, change)| *change != DiskChange::Unchanged)
+            .collect::<Vec<(Uuid, DiskChange)>>();
+        for (id, change) in changes {
+            let (title, modified) = match self.buffers.get(&id) {
+                Some(buffer) => (buffer.title.clone(), buffer.is_modified()),
+                None => continue,
+            };
+            match change {
+                DiskChange::Deleted => {
+                    if let Some(buffer) = self.buffers.get_mut(&id) {
+                        buffer.disk_state = None;
+                    }
+                    self.command_text =
+                        Some(format!("E211: File \"{}\" no longer available", title));
+                }
+                DiskChange::Changed if self.options.autoread && !modified => {
+                    match self.reload_buffer(id) {
+                        Ok(()) => self.command_text = Some(format!("\"{}\" reloaded", title)),
+                        Err(e) => self.command_text = Some(e.to_string()),
+                    }
+                }
+                DiskChange::Changed => {
+                    self.prompt(
+                        ConfirmAction::FileChanged(id),
+                        format!(
+                            "W11: Warning: File \"{}\" has changed since editing started. [R]eload, [K]eep, [D]iff?",
+                            title
+                        ),
+                    );
+                    return;
+                }
+                DiskChange::Unchanged => (),
+            }
+        }
+    }
+
+    pub fn reload_buffer(&mut self, id: Uuid) -> AnyHowResult<()> {
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            buffer.reload()?;
+            for window in self
+                .windows
+                .values_mut()
+                .chain(self.tabs.iter_mut().flat_map(|t| t.windows.values_mut()))
+                .filter(|w| w.buffer_id == id)
+            {
+                window.cache_window_content(&buffer.text);
+                if window.id == self.current_window_id {
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    window.update(change);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn open_disk_diff(&mut self, id: Uuid) -> AnyHowResult<()> {
+        let (title, file_path, text) = match self.buffers.get(&id) {
+            Some(buffer) => (
+                buffer.title.clone(),
+                buffer.file_path.clone().unwrap_or_default(),
+                buffer.text.to_string(),
+            ),
+            None => return Ok(()),
+        };
+        let disk_text = std::fs::read_to_string(file_path)?;
+        let diff = diff::diff_lines(
+            &disk_text.lines().collect::<Vec<&str>>(),
+            &text.lines().collect::<Vec<&str>>(),
+        );
+        let buffer = Buffer::new_scratch(&format!("[Diff] {}", title), &diff.join("\n"))?;
+        self.split_with_buffer(buffer, Direction::Horizontal)?;
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            buffer.record_disk_state();
+        }
+        Ok(())
+    }
+
+    /// Lays the windows out again for a terminal resized to `width` by `height`, scaling every
+    /// window so splits keep their proportions.
+    pub fn on_resize(&mut self, width: u16, height: u16) {
+        let size = Rect::new(0, 0, width, height);
+        let _ = self.terminal.resize(size);
+        self.ui.resize(size, !self.tabs.is_empty());
+        self.apply_layout();
+        self.render_ui();
+    }
+
+    /// Clicks focus the window under the pointer and put the cursor on the text clicked,
+    /// dragging selects or, from a window's header row or first column, resizes the window
+    /// beyond that border, and the wheel scrolls the window under the pointer.
+    pub fn on_mouse(&mut self, event: MouseEvent) {
+        if !self.options.mouse || self.mode == Mode::Command || self.mode == Mode::Confirm {
+            return;
+        }
+        let (col, row) = (event.column, event.row);
+        let areas = self.window_layout.areas(self.ui.text_area);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(tab_area) = self.ui.tab_area.filter(|area| area.y == row) {
+                    self.click_tab_line(sub_safe(col, tab_area.x) as usize);
+                } else if let Some(border) = layout::border_at(&areas, col, row) {
+                    self.mouse_drag = Some(MouseDrag::Border(border.0, border.1));
+                } else if let Some(window_id) = layout::window_at(&areas, col, row) {
+                    if self.mode == Mode::Visual {
+                        if let Some(buffer) = self.get_mut_buffer() {
+                            buffer.end_selection();
+                        }
+                        self.set_normal_mode();
+                    }
+                    self.focus_window(window_id);
+                    self.move_to_mouse(col, row);
+                    self.mouse_drag = Some(MouseDrag::Select);
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => match self.mouse_drag {
+                Some(MouseDrag::Select) => {
+                    if self.mode != Mode::Visual {
+                        self.set_visual_mode(SelectMode::Char);
+                    }
+                    self.move_to_mouse(col, row);
+                }
+                Some(MouseDrag::Border(window_id, direction)) => {
+                    let start = match self.windows.get(&window_id).and_then(|w| w.area) {
+                        Some(area) if direction == Direction::Vertical => area.y,
+                        Some(area) => area.x,
+                        None => return,
+                    };
+                    let end = if direction == Direction::Vertical {
+                        row
+                    } else {
+                        col
+                    };
+                    let size = sub_safe(end, start);
+                    self.window_layout
+                        .resize(self.ui.text_area, window_id, direction, &|_| size);
+                    self.apply_layout();
+                }
+                None => return,
+            },
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.mouse_drag = None;
+                return;
+            }
+            MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+                if let Some(window_id) = layout::window_at(&areas, col, row) {
+                    let scroll = if event.kind == MouseEventKind::ScrollDown {
+                        Scroll::LineDown
+                    } else {
+                        Scroll::LineUp
+                    };
+                    self.scroll_window(window_id, scroll);
+                }
+            }
+            _ => return,
+        }
+        self.render_ui();
+    }
+
+    /// Puts the cursor on the text at screen cell (`col`, `row`), or the nearest text in the
+    /// current window when the cell is outside it.
+    fn move_to_mouse(&mut self, col: u16, row: u16) {
+        let (x_offset, y_offset, height) = match self.get_window() {
+            Some(window) => (window.x_offset, window.y_offset, window.text_height()),
+            None => return,
+        };
+        let text_row = (sub_safe(row, y_offset) as usize).min(sub_safe(height, 1));
+        let text_col = sub_safe(col, x_offset) as usize;
+        if let Some(buffer) = self.get_mut_buffer() {
+            buffer.move_to_screen(text_row, text_col);
+        }
+    }
+
+    /// Scrolls `window_id` three lines, as a wheel step does, without leaving the current
+    /// window.
+    fn scroll_window(&mut self, window_id: Uuid, scroll: Scroll) {
+        let (current, previous) = (self.current_window_id, self.previous_window_id);
+        self.focus_window(window_id);
+        let scrolloff = self.options.scrolloff;
+        let height = self.get_window().map(|w| w.text_height());
+        if let (Some(buffer), Some(height)) = (self.get_mut_buffer(), height) {
+            buffer.page_size = height;
+            for _ in 0..3 {
+                buffer.scroll(scroll, scrolloff);
+            }
+        }
+        self.focus_window(current);
+        self.previous_window_id = previous;
+    }
+
+    /// Shows the tab whose label covers column `col` of the tab line.
+    fn click_tab_line(&mut self, col: usize) {
+        let mut end = 0;
+        for (idx, (label, _)) in self.tab_labels().into_iter().enumerate() {
+            end += label.chars().count();
+            if col < end {
+                return self.go_to_tab(idx);
+            }
+        }
+    }
+
+    pub fn on_focus_gained(&mut self) {
+        self.check_external_changes();
+        self.render_ui();
+    }
+
+    pub fn report_swap_conflict(&mut self, buffer_id: Uuid) {
+        if let Some(buffer) = self.buffers.get(&buffer_id) {
+            if let (Some(info), Some(swap_path)) = (&buffer.swap_conflict, buffer.swap_path()) {
+                self.command_text = Some(swap::attention_message(&swap_path, info));
+            }
+        }
+    }
+
+    pub fn on_idle(&mut self) {
+        if self.options.checkinterval > 0
+            && self.last_disk_check.elapsed() >= Duration::from_millis(self.options.checkinterval)
+        {
+            self.last_disk_check = Instant::now();
+            if self.pending_confirm.is_none() {
+                self.check_external_changes();
+                if self.pending_confirm.is_some() {
+                    self.render_ui();
+                }
+            }
+        }
+        if !self.options.swapfile
+            || self.last_swap_write.elapsed() < Duration::from_millis(self.options.updatetime)
+        {
+            return;
+        }
+        for buffer in self.buffers.values_mut() {
+            if buffer.needs_swap_write() {
+                if let Err(e) = buffer.write_swap() {
+                    log::warn!("failed to write swap file for {}: {}", buffer.title, e);
+                }
             }
         }
+        self.last_swap_write = Instant::now();
     }
 
-    pub fn get_current_node_id(&self) -> AnyHowResult<Option<NodeId>> {
-        let current_window_id = self.current_window_id;
-        if let Some(root_node_id) = self.window_layout.root_node_id() {
-            for (child, id) in self
-                .window_layout
-                .traverse_pre_order(root_node_id)?
-                .zip(self.window_layout.traverse_pre_order_ids(root_node_id)?)
-            {
-                if child.data().1 == current_window_id {
-                    return Ok(Some(id));
-                }
-            }
+    pub fn refresh_current_window(&mut self) {
+        let highlighting = self.highlighting.clone();
+        if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+            window.large_file = buffer.large_file;
+            window.set_highlight(&highlighting, buffer);
+            let change = WindowChange {
+                id: buffer.id,
+                x_pos: buffer.x_pos,
+                y_pos: buffer.y_pos,
+                title: Some(buffer.title.clone()),
+                page_size: buffer.page_size,
+                current_page: buffer.current_page,
+                ..WindowChange::default()
+            };
+            window.cache_window_content(&buffer.text);
+            window.update(change);
         }
-        Ok(None)
     }
 
-    pub fn get_parent_node(&self, current_node: NodeId) -> Option<((Rect, Uuid), NodeId)> {
-        if let Ok(node) = self.window_layout.get(&current_node) {
-            if let Some(parent_node_id) = node.parent() {
-                if let Ok(parent_node) = self.window_layout.get(parent_node_id) {
-                    return Some((parent_node.data().clone(), parent_node_id.clone()));
-                }
+    fn on_quit_all(&mut self) {
+        for (_, mut buffer) in self.buffers.drain() {
+            buffer.remove_swap();
+        }
+        let _ = execute!(stdout(), terminal::Clear(ClearType::All));
+        self.should_quit = true;
+    }
+
+    /// Re-highlights every window after the color scheme changed.
+    pub fn apply_highlighting(&mut self) {
+        self.ui.theme = self.highlighting.ui_theme.clone();
+        for window in self
+            .windows
+            .values_mut()
+            .chain(self.tabs.iter_mut().flat_map(|t| t.windows.values_mut()))
+        {
+            if let Some(buffer) = self.buffers.get(&window.buffer_id) {
+                window.set_highlight(&self.highlighting, buffer);
+                window.cache_window_content(&buffer.text);
             }
         }
-        None
     }
 
-    pub fn get_sibling_node(&self, parent_node_id: NodeId) -> AnyHowResult<Option<NodeId>> {
-        for (child, id) in self
-            .window_layout
-            .children(&parent_node_id)?
-            .zip(self.window_layout.children_ids(&parent_node_id)?)
+    fn completion_candidates(&self, command: &str, arg: &str) -> Vec<String> {
+        match command {
+            "colorscheme" | "colo" => self
+                .highlighting
+                .theme_names()
+                .into_iter()
+                .filter(|name| name.starts_with(arg))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn complete_command_line(&mut self) {
+        let text = self.command_text.clone().unwrap_or_default();
+        if let Some(completion) = self
+            .completion
+            .as_mut()
+            .filter(|c| c.current() == text && c.matches.len() > 1)
         {
-            if child.data().1 != self.current_window_id {
-                return Ok(Some(id.clone()));
+            completion.index = (completion.index + 1) % completion.matches.len();
+            self.command_text = Some(completion.current());
+            return;
+        }
+        let (command, arg) = text.split_once(' ').unwrap_or((text.as_str(), ""));
+        let matches = self.completion_candidates(command.trim(), arg.trim_start());
+        if matches.is_empty() {
+            self.completion = None;
+            return;
+        }
+        let completion = Completion {
+            prefix: format!("{} ", command.trim()),
+            matches,
+            index: 0,
+        };
+        self.command_text = Some(completion.current());
+        self.completion = Some(completion);
+    }
+
+    pub fn push_history(&mut self, kind: HistoryKind, entry: &str) {
+        let history = match kind {
+            HistoryKind::Command => &mut self.command_history,
+            HistoryKind::Search => &mut self.search_history,
+        };
+        let entry = entry.trim();
+        if entry.is_empty() || history.last().map(|l| l == entry).unwrap_or(false) {
+            return;
+        }
+        history.push(entry.to_owned());
+    }
+
+    pub fn open_command_line_window(&mut self, kind: HistoryKind) -> AnyHowResult<()> {
+        if self.command_line_window.is_some() {
+            return Ok(());
+        }
+        let (title, history) = match kind {
+            HistoryKind::Command => ("[Command Line]", &self.command_history),
+            HistoryKind::Search => ("[Search History]", &self.search_history),
+        };
+        let mut lines = history.clone();
+        lines.push("".to_string());
+        let buffer = Buffer::new_scratch(title, &lines.join("\n"))?;
+        let buffer_id = buffer.id;
+        self.split_with_buffer(buffer, Direction::Vertical)?;
+        self.command_line_window = Some((buffer_id, kind));
+        if let (Some(window), Some(buffer)) = self.get_mut_pair() {
+            buffer.move_to_last_line();
+            let change = WindowChange {
+                id: buffer.id,
+                x_pos: buffer.x_pos,
+                y_pos: buffer.y_pos,
+                title: Some(buffer.title.clone()),
+                page_size: buffer.page_size,
+                current_page: buffer.current_page,
+                ..WindowChange::default()
+            };
+            window.update(change);
+        }
+        Ok(())
+    }
+
+    pub fn execute_command_line_window(&mut self) {
+        let kind = match self.command_line_window {
+            Some((id, kind)) if id == self.current_buffer_id => kind,
+            _ => return,
+        };
+        let line = self
+            .get_buffer()
+            .map(|b| b.current_line_chars().iter().collect::<String>())
+            .unwrap_or_default();
+        self.close_command_line_window();
+        match kind {
+            HistoryKind::Command => {
+                self.command_text = Some(line);
+                self.handle_command_token(CommandToken::Enter);
             }
+            HistoryKind::Search => self.search(&line),
         }
-        Ok(None)
     }
 
-    fn on_quit(&mut self) {
-        let id = self.current_buffer_id;
-        self.buffers.remove(&id);
-        if self.buffers.is_empty() {
-            let _ = execute!(stdout(), terminal::Clear(ClearType::All));
-            self.should_quit = true;
-        } else {
-            if let Ok(Some(current_node)) = self.get_current_node_id() {
-                if let Some(((parent_rect, _), parent_node_id)) =
-                    self.get_parent_node(current_node.clone())
-                {
-                    if let Ok(Some(sibling_node_id)) = self.get_sibling_node(parent_node_id.clone())
-                    {
-                        if let Ok((_, sibling_window_id)) = self
-                            .window_layout
-                            .get(&sibling_node_id)
-                            .map(|n| n.data().clone())
-                        {
-                            let _ = self
-                                .window_layout
-                                .get_mut(&parent_node_id)
-                                .map(|n| (n.data().0, sibling_window_id));
-                            let _ = self
-                                .window_layout
-                                .remove_node(current_node.clone(), LiftChildren);
-                            let _ = self
-                                .window_layout
-                                .remove_node(sibling_node_id.clone(), LiftChildren);
-                            self.windows.remove(&id);
-                            self.current_window_id = sibling_window_id;
-                            self.current_buffer_id = sibling_window_id;
-                            self.get_mut_window().map(|w| w.area = Some(parent_rect));
-                            self.reorder_windows();
-                        }
-                    }
+    /// Runs a `/` search from the cursor and records it in the search history.
+    pub fn search(&mut self, pattern: &str) {
+        self.push_history(HistoryKind::Search, pattern);
+        if let Some(buffer) = self.get_mut_buffer() {
+            if buffer.search_forward(pattern) {
+                let change = WindowChange {
+                    id: buffer.id,
+                    x_pos: buffer.x_pos,
+                    y_pos: buffer.y_pos,
+                    title: Some(buffer.title.clone()),
+                    page_size: buffer.page_size,
+                    current_page: buffer.current_page,
+                    ..WindowChange::default()
+                };
+                if let Some(window) = self.get_mut_window() {
+                    window.update(change);
                 }
+            } else {
+                self.command_text = Some(format!("E486: Pattern not found: {}", pattern));
             }
         }
-        self.set_normal_mode();
         self.render_ui();
     }
 
+    pub fn close_command_line_window(&mut self) {
+        if let Some((id, _)) = self.command_line_window.take() {
+            if id == self.current_buffer_id {
+                self.on_quit();
+            }
+        }
+    }
+
     pub fn handle_normal_token(&mut self, token: NormalToken) {
         match token {
             NormalToken::SwitchToCommand => {
@@ -364,6 +1663,12 @@ impl App {
                 self.set_command_mode();
                 self.render_ui();
             }
+            NormalToken::SwitchToSearch => {
+                self.command_text = Some("".to_string());
+                self.set_command_mode();
+                self.command_line_kind = HistoryKind::Search;
+                self.render_ui();
+            }
             NormalToken::SwitchToInsert => {
                 self.set_insert_mode();
                 self.render_ui();
@@ -405,8 +1710,15 @@ impl App {
                 self.render_ui();
             }
             NormalToken::Paste => {
+                let block_register = self.block_register.clone();
                 if let Some(buffer) = self.get_mut_buffer() {
-                    buffer.paste_text();
+                    // A yanked block pastes as one while it is still what the clipboard holds.
+                    match block_register
+                        .filter(|lines| buffer.clipboard_text() == Some(lines.join("\n")))
+                    {
+                        Some(lines) => buffer.paste_block(&lines),
+                        None => buffer.paste_text(),
+                    }
                     let change = WindowChange {
                         id: buffer.id,
                         x_pos: buffer.x_pos,
@@ -465,7 +1777,8 @@ impl App {
                         current_page: buffer.current_page,
                         ..WindowChange::default()
                     };
-                    let _ = window.remove_cache_line(removed_line_index as usize);
+                    window.remove_cache_line(removed_line_index);
+                    buffer.folds.remove_line(removed_line_index);
                     window.cache_line_numbers(&buffer.text);
                     window.update(change)
                 }
@@ -473,12 +1786,157 @@ impl App {
                 self.render_ui();
             }
             NormalToken::Visual => {
-                self.set_visual_mode();
+                self.set_visual_mode(SelectMode::Char);
                 self.render_ui();
             }
             NormalToken::VisualLine => {
+                self.set_visual_mode(SelectMode::Line);
+                self.render_ui();
+            }
+            NormalToken::VisualBlock => {
+                if self.mode == Mode::Visual {
+                    self.switch_visual_mode(SelectMode::Block);
+                } else {
+                    self.set_visual_mode(SelectMode::Block);
+                }
+                self.render_ui();
+            }
+            NormalToken::Reselect => {
+                if self.get_mut_buffer().map(|b| b.reselect()).unwrap_or(false) {
+                    self.mode = Mode::Visual;
+                } else {
+                    self.command_text = Some(String::from("E20: Mark not set"));
+                }
+                self.render_ui();
+            }
+            NormalToken::SetWindow(window_order) => {
+                log::debug!("in set window {:?}", window_order);
+                if let Some(window) = self
+                    .windows
+                    .values()
+                    .filter(|w| w.order == window_order)
+                    .nth(0)
+                {
+                    let window_id = window.id;
+                    self.focus_window(window_id);
+                }
+                self.render_ui();
+            }
+            NormalToken::WindowLeft => {
+                self.focus_neighbour(Edge::Left);
+                self.render_ui();
+            }
+            NormalToken::WindowRight => {
+                self.focus_neighbour(Edge::Right);
+                self.render_ui();
+            }
+            NormalToken::WindowUp => {
+                self.focus_neighbour(Edge::Top);
+                self.render_ui();
+            }
+            NormalToken::WindowDown => {
+                self.focus_neighbour(Edge::Bottom);
+                self.render_ui();
+            }
+            NormalToken::NextWindow | NormalToken::PrevWindow => {
+                self.cycle_window(token == NormalToken::NextWindow);
+                self.render_ui();
+            }
+            NormalToken::LastAccessedWindow => {
+                if let Some(window_id) = self
+                    .previous_window_id
+                    .filter(|id| self.windows.contains_key(id))
+                {
+                    self.focus_window(window_id);
+                }
+                self.render_ui();
+            }
+            NormalToken::TopWindow | NormalToken::BottomWindow => {
+                let windows = self.window_layout.windows();
+                let window = if token == NormalToken::TopWindow {
+                    windows.first()
+                } else {
+                    windows.last()
+                };
+                if let Some(window_id) = window.cloned() {
+                    self.focus_window(window_id);
+                }
+                self.render_ui();
+            }
+            NormalToken::ExchangeWindow => {
+                self.exchange_window();
+                self.render_ui();
+            }
+            NormalToken::RotateWindows(downwards) => {
+                if let Err(e) = self.rotate_windows(downwards) {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            NormalToken::MoveWindow(edge) => {
+                self.window_layout
+                    .move_to_edge(self.current_window_id, edge);
+                self.apply_layout();
+                self.render_ui();
+            }
+            NormalToken::ResizeWindow(direction, delta) => {
+                self.resize_window(direction, |size| {
+                    (size as i32 + delta).max(0).min(u16::MAX as i32) as u16
+                });
+                self.render_ui();
+            }
+            NormalToken::MaximizeWindow(direction) => {
+                self.resize_window(direction, |_| u16::MAX);
+                self.render_ui();
+            }
+            NormalToken::EqualizeWindows => {
+                self.window_layout.equalize();
+                self.apply_layout();
+                self.render_ui();
+            }
+            NormalToken::OnlyWindow => {
+                if let Err(e) = self.close_other_windows(false) {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            NormalToken::NextTab | NormalToken::PrevTab => {
+                let count = self.tabs.len() + 1;
+                let next = if token == NormalToken::NextTab {
+                    (self.current_tab + 1) % count
+                } else {
+                    (self.current_tab + count - 1) % count
+                };
+                self.go_to_tab(next);
+                self.render_ui();
+            }
+            NormalToken::GoToTab(number) => {
+                self.go_to_tab(sub_safe(number, 1));
+                self.render_ui();
+            }
+            NormalToken::AlternateBuffer => {
+                if let Err(e) = self.switch_to_alternate() {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            NormalToken::CommandLineWindow(kind) => {
+                if let Err(e) = self.open_command_line_window(kind) {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            NormalToken::FoldOpen | NormalToken::FoldClose | NormalToken::FoldToggle => {
+                let mut result = Ok(());
                 if let Some(buffer) = self.get_mut_buffer() {
-                    buffer.select_line();
+                    buffer.update_folds();
+                    let line = buffer.y_pos;
+                    result = match token {
+                        NormalToken::FoldOpen => buffer.folds.open(line),
+                        NormalToken::FoldClose => buffer.folds.close(line),
+                        _ => buffer.folds.toggle(line),
+                    };
+                    buffer.leave_closed_fold();
                     let change = WindowChange {
                         id: buffer.id,
                         x_pos: buffer.x_pos,
@@ -488,24 +1946,68 @@ impl App {
                         current_page: buffer.current_page,
                         ..WindowChange::default()
                     };
-                    self.get_mut_window().map(|w| w.update(change));
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
                 }
-
                 self.render_ui();
             }
-            NormalToken::SetWindow(window_order) => {
-                log::debug!("in set window {:?}", window_order);
-                if let Some(window) = self
-                    .windows
-                    .values()
-                    .filter(|w| w.order == window_order)
-                    .nth(0)
-                {
-                    self.current_window_id = window.id;
-                    self.current_buffer_id = window.id;
+            NormalToken::Scroll(scroll) => {
+                let scrolloff = self.options.scrolloff;
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.scroll(scroll, scrolloff);
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+                self.render_ui();
+            }
+            NormalToken::FoldOpenAll | NormalToken::FoldCloseAll => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.update_folds();
+                    buffer.folds.set_all(token == NormalToken::FoldCloseAll);
+                    buffer.leave_closed_fold();
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
                 }
                 self.render_ui();
             }
+            NormalToken::Enter => {
+                self.execute_command_line_window();
+            }
+            NormalToken::Esc => {
+                if self.mode == Mode::Visual {
+                    if let Some(buffer) = self.get_mut_buffer() {
+                        buffer.end_selection();
+                    }
+                    self.set_normal_mode();
+                    self.render_ui();
+                } else if self.command_line_window.is_some() {
+                    self.close_command_line_window();
+                }
+            }
             _ => (),
         }
     }
@@ -532,8 +2034,145 @@ impl App {
 
     pub fn handle_command_token(&mut self, token: CommandToken) {
         match token {
-            CommandToken::Write => {
-                self.get_mut_buffer().and_then(|b| b.on_save().ok());
+            CommandToken::Write
+            | CommandToken::WriteQuit
+            | CommandToken::WriteQuitIfModified
+            | CommandToken::WriteTo(_)
+            | CommandToken::SaveAs(_)
+            | CommandToken::WriteAll
+            | CommandToken::WriteQuitAll
+                if self.confirm_overwrite(&token) =>
+            {
+                self.render_ui()
+            }
+            CommandToken::Write | CommandToken::ForceWrite => {
+                let result = self.write_buffer(self.current_buffer_id, None, false);
+                self.set_normal_mode();
+                self.command_text = Some(result.unwrap_or_else(|e| e.to_string()));
+                self.render_ui();
+            }
+            CommandToken::WriteTo(file_name) => {
+                let result = self.write_buffer(self.current_buffer_id, Some(file_name), false);
+                self.set_normal_mode();
+                self.command_text = Some(result.unwrap_or_else(|e| e.to_string()));
+                self.render_ui();
+            }
+            CommandToken::AppendTo(file_name) => {
+                let result = self.write_buffer(self.current_buffer_id, Some(file_name), true);
+                self.set_normal_mode();
+                self.command_text = Some(result.unwrap_or_else(|e| e.to_string()));
+                self.render_ui();
+            }
+            CommandToken::SaveAs(file_name) => {
+                let result = self.save_as(file_name);
+                self.set_normal_mode();
+                self.command_text = Some(result.unwrap_or_else(|e| e.to_string()));
+                self.render_ui();
+            }
+            CommandToken::WriteQuitIfModified
+                if !self.get_buffer().map(|b| b.is_modified()).unwrap_or(false) =>
+            {
+                self.on_quit()
+            }
+            CommandToken::WriteQuit | CommandToken::WriteQuitIfModified => {
+                match self.write_buffer(self.current_buffer_id, None, false) {
+                    Ok(_) => self.on_quit(),
+                    Err(e) => {
+                        self.set_normal_mode();
+                        self.command_text = Some(e.to_string());
+                        self.render_ui();
+                    }
+                }
+            }
+            CommandToken::WriteAll => {
+                let result = self.write_all();
+                self.set_normal_mode();
+                self.command_text = Some(result.unwrap_or_else(|e| e.to_string()));
+                self.render_ui();
+            }
+            CommandToken::WriteQuitAll => match self.write_all() {
+                Ok(_) => self.on_quit_all(),
+                Err(e) => {
+                    self.set_normal_mode();
+                    self.command_text = Some(e.to_string());
+                    self.render_ui();
+                }
+            },
+            CommandToken::Edit(_) if !self.can_abandon() => {
+                self.set_normal_mode();
+                self.command_text = Some(NO_WRITE_ERR.to_string());
+                self.render_ui();
+            }
+            CommandToken::Edit(file_name) => {
+                let result = self.edit_file(file_name);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::Set(arg) => {
+                let highlight_mode = self
+                    .get_buffer()
+                    .map(|b| (b.filetype.clone(), b.syntax_tree.is_some()));
+                let mouse = self.options.mouse;
+                let result = match self.get_mut_buffer().and_then(|b| b.set_option(&arg)) {
+                    Some(result) => result,
+                    None => self.options.set(&arg),
+                };
+                if mouse != self.options.mouse {
+                    let _ = if self.options.mouse {
+                        execute!(stdout(), EnableMouseCapture)
+                    } else {
+                        execute!(stdout(), DisableMouseCapture)
+                    };
+                }
+                if highlight_mode
+                    != self
+                        .get_buffer()
+                        .map(|b| (b.filetype.clone(), b.syntax_tree.is_some()))
+                {
+                    self.refresh_current_window();
+                }
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::Recover => {
+                let result = self.get_mut_buffer().map(|b| b.recover());
+                self.set_normal_mode();
+                match result {
+                    Some(Ok(())) => {
+                        self.refresh_current_window();
+                        let swap_path = self
+                            .get_buffer()
+                            .filter(|b| b.swap_conflict.is_some())
+                            .and_then(|b| b.swap_path());
+                        if let Some(swap_path) = swap_path {
+                            self.prompt(
+                                ConfirmAction::DeleteSwap(self.current_buffer_id),
+                                format!(
+                                    "Recovery completed. Delete swap file \"{}\"? (y/n)",
+                                    swap_path.display()
+                                ),
+                            );
+                        } else {
+                            self.command_text = Some("Recovery completed".to_string());
+                        }
+                    }
+                    Some(Err(e)) => self.command_text = Some(e.to_string()),
+                    None => (),
+                }
+                self.render_ui();
+            }
+            CommandToken::Reload => {
+                let result = self.edit_file(None);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
                 self.render_ui();
             }
             CommandToken::GoToLine(line_number) => {
@@ -570,7 +2209,8 @@ impl App {
                     for idx in start..end {
                         buffer.move_to_line_number(idx);
                         buffer.delete_line_direct();
-                        let _ = window.remove_cache_line(sub_safe(idx as u16, 1) as usize);
+                        window.remove_cache_line(sub_safe(idx, 1));
+                        buffer.folds.remove_line(sub_safe(idx, 1));
                     }
 
                     let change = WindowChange {
@@ -591,38 +2231,160 @@ impl App {
                 }
             }
             CommandToken::Split(file_name) => {
-                let _ = self.new_split(file_name, Direction::Vertical);
+                let result = self.new_split(file_name, Direction::Vertical);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::VerticalSplit(file_name) => {
+                let result = self.new_split(file_name, Direction::Horizontal);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::Esc => {
+                self.set_normal_mode();
+                self.render_ui();
+            }
+            CommandToken::Append(chars) => {
+                self.command_text = self.command_text.clone().map(|mut t| {
+                    t.push_str(&chars);
+                    t
+                });
+                self.render_ui();
+            }
+            CommandToken::Remove => {
+                self.command_text = self.command_text.clone().map(|mut t| {
+                    t.truncate(sub_safe(t.len(), 1));
+                    t
+                });
+                self.render_ui();
+            }
+            CommandToken::NoOp => (),
+            CommandToken::Quit if self.get_buffer().map(|b| b.is_modified()).unwrap_or(false) => {
                 self.set_normal_mode();
+                self.command_text = Some(NO_WRITE_ERR.to_string());
                 self.render_ui();
             }
-            CommandToken::VerticalSplit(file_name) => {
-                let _ = self.new_split(file_name, Direction::Horizontal);
+            CommandToken::Quit if self.windows.len() <= 1 && self.tabs.is_empty() => {
+                let hidden_modified = self
+                    .buffers
+                    .values()
+                    .find(|b| b.id != self.current_buffer_id && b.is_modified())
+                    .map(|b| b.title.clone());
+                match hidden_modified {
+                    Some(title) => {
+                        self.set_normal_mode();
+                        self.command_text = Some(format!(
+                            "E162: No write since last change for buffer \"{}\"",
+                            title
+                        ));
+                        self.render_ui();
+                    }
+                    None => self.on_quit(),
+                }
+            }
+            CommandToken::Quit | CommandToken::ForceQuit => self.on_quit(),
+            CommandToken::QuitAll => {
+                let modified = self
+                    .buffers
+                    .values()
+                    .filter(|b| b.is_modified())
+                    .map(|b| b.title.clone())
+                    .collect::<Vec<String>>();
+                if modified.is_empty() {
+                    self.on_quit_all();
+                } else {
+                    self.prompt(
+                        ConfirmAction::QuitAll,
+                        format!(
+                            "No write since last change for: {}. Quit anyway? (y/n)",
+                            modified.join(", ")
+                        ),
+                    );
+                    self.render_ui();
+                }
+            }
+            CommandToken::ForceQuitAll => self.on_quit_all(),
+            CommandToken::Confirm(choice) => {
+                let action = self.pending_confirm.take();
+                self.set_normal_mode();
+                match (action, choice) {
+                    (Some(ConfirmAction::QuitAll), 'y') => self.on_quit_all(),
+                    (Some(ConfirmAction::DeleteSwap(id)), choice) => {
+                        if let Some(buffer) = self.buffers.get_mut(&id) {
+                            if choice == 'y' {
+                                buffer.delete_stale_swap();
+                            } else {
+                                buffer.swap_conflict = None;
+                            }
+                        }
+                        self.render_ui();
+                    }
+                    (Some(ConfirmAction::FileChanged(id)), 'r') => {
+                        if let Err(e) = self.reload_buffer(id) {
+                            self.command_text = Some(e.to_string());
+                        }
+                        self.render_ui();
+                    }
+                    (Some(ConfirmAction::FileChanged(id)), 'k') => {
+                        if let Some(buffer) = self.buffers.get_mut(&id) {
+                            buffer.record_disk_state();
+                        }
+                        self.render_ui();
+                    }
+                    (Some(ConfirmAction::FileChanged(id)), 'd') => {
+                        if let Err(e) = self.open_disk_diff(id) {
+                            self.command_text = Some(e.to_string());
+                        }
+                        self.render_ui();
+                    }
+                    (Some(ConfirmAction::Overwrite(ids, token)), 'y') => {
+                        for id in ids {
+                            if let Some(buffer) = self.buffers.get_mut(&id) {
+                                buffer.record_disk_state();
+                            }
+                        }
+                        self.handle_command_token(token);
+                    }
+                    _ => self.render_ui(),
+                }
+            }
+            CommandToken::CheckTime => {
                 self.set_normal_mode();
+                self.check_external_changes();
                 self.render_ui();
             }
-            CommandToken::Esc => {
+            CommandToken::ColorScheme(None) => {
                 self.set_normal_mode();
+                self.command_text = Some(self.highlighting.theme_name.clone());
                 self.render_ui();
             }
-            CommandToken::Append(chars) => {
-                self.command_text = self.command_text.clone().map(|mut t| {
-                    t.push_str(&chars);
-                    t
-                });
+            CommandToken::ColorScheme(Some(name)) => {
+                let result = self.highlighting.set_theme(&name);
+                self.set_normal_mode();
+                match result {
+                    Ok(()) => self.apply_highlighting(),
+                    Err(e) => self.command_text = Some(e.to_string()),
+                }
                 self.render_ui();
             }
-            CommandToken::Remove => {
-                self.command_text = self.command_text.clone().map(|mut t| {
-                    t.truncate(sub_safe(t.len() as u16, 1) as usize);
-                    t
-                });
+            CommandToken::Complete => {
+                self.complete_command_line();
                 self.render_ui();
             }
-            CommandToken::NoOp => (),
-            CommandToken::Quit => self.on_quit(),
-            CommandToken::TabNew => (),
             CommandToken::Enter => {
-                if let Some(command_text) = &self.command_text {
+                if self.command_line_kind == HistoryKind::Search {
+                    let pattern = self.command_text.clone().unwrap_or_default();
+                    self.set_normal_mode();
+                    return self.search(&pattern);
+                }
+                if let Some(command_text) = self.command_text.clone() {
+                    self.push_history(HistoryKind::Command, &command_text);
                     if let Ok(Token::Command(command)) = get_token_from_chars(
                         &Mode::Command,
                         &format!(":{}", command_text).chars().collect::<Vec<char>>(),
@@ -631,12 +2393,98 @@ impl App {
                     }
                 }
             }
-            CommandToken::SetBuffer(id) => {
-                if let Some(_buffer) = self.buffers.get(&id) {
-                    self.current_buffer_id = id;
+            CommandToken::ListBuffers => {
+                self.set_normal_mode();
+                if let Err(e) = self.open_buffer_list() {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::Buffer(arg) => {
+                let result =
+                    buffer_list::find(&self.buffers, &arg).and_then(|id| self.switch_buffer(id));
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::NextBuffer | CommandToken::PrevBuffer => {
+                let forward = token == CommandToken::NextBuffer;
+                let result =
+                    match buffer_list::cycle(&self.buffers, self.current_buffer_id, forward) {
+                        Some(id) => self.switch_buffer(id),
+                        None => Ok(()),
+                    };
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::DeleteBuffer(arg, force) => {
+                let result = self.delete_buffer(arg, force, false);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::WipeBuffer(arg, force) => {
+                let result = self.delete_buffer(arg, force, true);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::Resize(arg) => self.resize_command(Direction::Vertical, &arg),
+            CommandToken::VerticalResize(arg) => self.resize_command(Direction::Horizontal, &arg),
+            CommandToken::Only(force) => {
+                let result = self.close_other_windows(force);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::TabNew(file_name) => {
+                let result = self.new_tab(file_name);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::TabClose(force) => {
+                let result = self.close_tab(force);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::TabOnly(force) => {
+                let result = self.close_other_tabs(force);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
+            }
+            CommandToken::TabMove(arg) => {
+                let result = tab::move_target(&arg, self.current_tab, self.tabs.len() + 1)
+                    .map(|target| self.current_tab = target);
+                self.set_normal_mode();
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
                 }
                 self.render_ui();
             }
+            CommandToken::SetBuffer(id) => {
+                self.show_buffer(id);
+                self.render_ui();
+            }
             CommandToken::SetMode(mode) => {
                 self.mode = mode.clone();
                 self.render_ui();
@@ -655,8 +2503,249 @@ impl App {
         };
     }
 
-    pub fn handle_operator_token(&mut self, _token: OperatorToken) {
-        unimplemented!()
+    /// The lines the visual selection covers, first and last.
+    fn selected_lines(&self) -> Option<(usize, usize)> {
+        self.get_buffer().and_then(|b| {
+            b.get_selected_range().map(|(start, end)| {
+                let first = b.text.char_to_line(start);
+                (first, b.text.char_to_line(sub_safe(end, 1)).max(first))
+            })
+        })
+    }
+
+    /// Applies an operator typed in visual mode to the selection, leaving visual mode.
+    pub fn handle_operator_token(&mut self, token: OperatorToken) {
+        if self.mode != Mode::Visual
+            || token == OperatorToken::Esc
+            || token == OperatorToken::Remove
+        {
+            return;
+        }
+        let selection = self
+            .get_buffer()
+            .and_then(|b| b.get_selected_range().map(|range| (range, b.select_mode)));
+        let ((start, mut end), select_mode) = match selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let block_operator = matches!(
+            token,
+            OperatorToken::Yank
+                | OperatorToken::Delete
+                | OperatorToken::Change
+                | OperatorToken::Uppercase
+                | OperatorToken::Lowercase
+                | OperatorToken::ToggleCase
+        );
+        let block = self.get_buffer().and_then(|b| b.selected_block());
+        if let (SelectMode::Block, true, Some(block)) = (select_mode, block_operator, block) {
+            return self.handle_block_operator(token, block);
+        }
+        if let Some(buffer) = self.get_mut_buffer() {
+            buffer.end_selection();
+            // Changing whole lines leaves an empty line to type on.
+            if token == OperatorToken::Change
+                && select_mode == SelectMode::Line
+                && end > start
+                && buffer.text.char(end - 1) == '\n'
+            {
+                end -= 1;
+            }
+        }
+        self.set_normal_mode();
+        self.handle_operator_token_range(token.clone(), start, end);
+        if token != OperatorToken::Change && token != OperatorToken::Fold {
+            if let Some(buffer) = self.get_mut_buffer() {
+                let start = start.min(buffer.text.len_chars());
+                buffer.y_pos = buffer.text.char_to_line(start);
+                buffer.x_pos = match token {
+                    OperatorToken::Indent | OperatorToken::UnIndent => 0,
+                    _ => start - buffer.text.line_to_char(buffer.y_pos),
+                };
+                let change = WindowChange {
+                    id: buffer.id,
+                    x_pos: buffer.x_pos,
+                    y_pos: buffer.y_pos,
+                    title: Some(buffer.title.clone()),
+                    page_size: buffer.page_size,
+                    current_page: buffer.current_page,
+                    ..WindowChange::default()
+                };
+                if let Some(window) = self.get_mut_window() {
+                    window.update(change);
+                }
+            }
+        }
+        self.render_ui();
+    }
+
+    /// Applies an operator to a blockwise selection. Shifting and folding take in its whole
+    /// lines instead and go through `handle_operator_token_range`.
+    fn handle_block_operator(&mut self, token: OperatorToken, block: Block) {
+        if let Some(buffer) = self.get_mut_buffer() {
+            buffer.end_selection();
+        }
+        self.set_normal_mode();
+        match token {
+            OperatorToken::Yank => {
+                self.block_register = self.get_mut_buffer().map(|b| {
+                    b.move_to_column(block.first, block.left);
+                    b.yank_block(&block)
+                });
+            }
+            OperatorToken::Delete => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.delete_block(&block);
+                }
+            }
+            OperatorToken::Change => {
+                let undo_depth = self.get_buffer().map(|b| b.past_states.len());
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.delete_block(&block);
+                }
+                self.start_block_insert(block, Some(block.left), false);
+                // The deletion and the insert are undone together.
+                if let (Some(insert), Some(depth)) = (self.block_insert.as_mut(), undo_depth) {
+                    insert.undo_depth = depth;
+                }
+            }
+            _ => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.replace_block_chars(&block, |c| change_case(&token, c));
+                }
+            }
+        }
+        self.render_ui();
+    }
+
+    /// Starts insert mode at `column` of the block's first line, or at its end without a
+    /// column, for `Esc` to repeat what gets typed on the block's other lines.
+    fn start_block_insert(&mut self, block: Block, column: Option<usize>, pad: bool) {
+        self.block_insert = self.get_mut_buffer().and_then(|buffer| {
+            let undo_depth = buffer.past_states.len();
+            buffer.set_states();
+            let start = buffer.insert_at_column(block.first, column, true, "")?;
+            buffer.y_pos = block.first;
+            buffer.x_pos = start - buffer.text.line_to_char(block.first);
+            Some(BlockInsert {
+                start,
+                first: block.first + 1,
+                last: block.last,
+                column,
+                pad,
+                undo_depth,
+            })
+        });
+        self.set_insert_mode();
+    }
+
+    /// Puts what was typed on the first line of a block insert on the block's other lines,
+    /// as long as it stayed on that one line. The whole insert is then one undo step.
+    fn finish_block_insert(&mut self, block_insert: BlockInsert) {
+        if let Some(buffer) = self.get_mut_buffer() {
+            let start = block_insert.start.min(buffer.text.len_chars());
+            let cursor = buffer.get_cursor_idx().min(buffer.text.len_chars());
+            if cursor > start && buffer.text.char_to_line(cursor) == buffer.text.char_to_line(start)
+            {
+                let text = buffer.text.slice(start..cursor).to_string();
+                for line in block_insert.first..=block_insert.last {
+                    buffer.insert_at_column(line, block_insert.column, block_insert.pad, &text);
+                }
+            }
+            buffer.join_states_since(block_insert.undo_depth);
+        }
+    }
+
+    pub fn handle_visual_token(&mut self, token: VisualToken) {
+        match token {
+            VisualToken::SwapEnds => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.swap_selection_ends();
+                }
+            }
+            VisualToken::Join => {
+                if let Some((first, last)) = self.selected_lines() {
+                    if let Some(buffer) = self.get_mut_buffer() {
+                        buffer.end_selection();
+                        buffer.join_lines(first, last.max(first + 1));
+                    }
+                }
+                self.set_normal_mode();
+            }
+            VisualToken::Command => {
+                if let Some((first, last)) = self.selected_lines() {
+                    if let Some(buffer) = self.get_mut_buffer() {
+                        buffer.end_selection();
+                    }
+                    self.command_text = Some(format!("{},{}", first + 1, last + 1));
+                    self.set_command_mode();
+                }
+            }
+            VisualToken::Lowercase => self.handle_operator_token(OperatorToken::Lowercase),
+            VisualToken::Uppercase => self.handle_operator_token(OperatorToken::Uppercase),
+            VisualToken::Char => self.switch_visual_mode(SelectMode::Char),
+            VisualToken::Line => self.switch_visual_mode(SelectMode::Line),
+            VisualToken::Insert | VisualToken::Append => {
+                let append = token == VisualToken::Append;
+                let block = self
+                    .get_buffer()
+                    .filter(|b| b.select_mode == SelectMode::Block)
+                    .and_then(|b| b.selected_block());
+                match block {
+                    Some(block) => {
+                        if let Some(buffer) = self.get_mut_buffer() {
+                            buffer.end_selection();
+                        }
+                        match (append, block.to_eol) {
+                            (false, _) => self.start_block_insert(block, Some(block.left), false),
+                            (true, true) => self.start_block_insert(block, None, false),
+                            (true, false) => {
+                                self.start_block_insert(block, Some(block.right), true)
+                            }
+                        }
+                    }
+                    None => {
+                        if let Some(buffer) = self.get_mut_buffer() {
+                            if let Some((start, end)) = buffer.get_selected_range() {
+                                buffer.end_selection();
+                                let at_newline = end > start && buffer.text.char(end - 1) == '\n';
+                                buffer.move_to_char(match (append, at_newline) {
+                                    (false, _) => start,
+                                    (true, true) => end - 1,
+                                    (true, false) => end,
+                                });
+                            }
+                        }
+                        self.set_insert_mode();
+                    }
+                }
+            }
+            VisualToken::Replace(c) => {
+                let block = self
+                    .get_buffer()
+                    .filter(|b| b.select_mode == SelectMode::Block)
+                    .and_then(|b| b.selected_block());
+                if let Some(buffer) = self.get_mut_buffer() {
+                    match (block, buffer.get_selected_range()) {
+                        (Some(block), _) => {
+                            buffer.end_selection();
+                            buffer.fill_block(&block, c);
+                        }
+                        (None, Some((start, end))) => {
+                            buffer.end_selection();
+                            buffer.replace_chars(start, end, |ch| match ch {
+                                '\n' => ch.to_string(),
+                                _ => c.to_string(),
+                            });
+                            buffer.move_to_char(start);
+                        }
+                        _ => (),
+                    }
+                }
+                self.set_normal_mode();
+            }
+        }
+        self.render_ui();
     }
 
     pub fn handle_operator_token_range(
@@ -675,22 +2764,74 @@ impl App {
                     .map(|b| b.delete_line_range(start_range, end_range));
             }
             OperatorToken::Change => {
-                unimplemented!()
-            }
-            OperatorToken::Indent => {
-                unimplemented!()
-            }
-            OperatorToken::UnIndent => {
-                unimplemented!()
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.delete_line_range(start_range, end_range);
+                    buffer.move_to_char(start_range);
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+                self.set_insert_mode();
+                self.render_ui();
             }
-            OperatorToken::Uppercase => {
-                unimplemented!()
+            OperatorToken::Indent | OperatorToken::UnIndent => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    let first = buffer.text.char_to_line(start_range.min(end_range));
+                    let last = buffer
+                        .text
+                        .char_to_line(sub_safe(start_range.max(end_range), 1))
+                        .max(first);
+                    buffer.indent_lines(first, last, token == OperatorToken::UnIndent);
+                }
+                self.render_ui();
             }
-            OperatorToken::Lowercase => {
-                unimplemented!()
+            OperatorToken::Uppercase | OperatorToken::Lowercase | OperatorToken::ToggleCase => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.replace_chars(
+                        start_range.min(end_range),
+                        start_range.max(end_range),
+                        |c| change_case(&token, c),
+                    );
+                }
+                self.render_ui();
             }
-            OperatorToken::ToggleCase => {
-                unimplemented!()
+            OperatorToken::Fold => {
+                let mut result = Ok(());
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.update_folds();
+                    let start_line = buffer.text.char_to_line(start_range.min(end_range));
+                    let end_line = buffer
+                        .text
+                        .char_to_line(sub_safe(start_range.max(end_range), 1))
+                        .max(start_line);
+                    result = buffer.folds.create(start_line, end_line);
+                    buffer.leave_closed_fold();
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+                if let Err(e) = result {
+                    self.command_text = Some(e.to_string());
+                }
+                self.render_ui();
             }
             OperatorToken::Esc => {
                 self.set_normal_mode();
@@ -709,11 +2850,24 @@ impl App {
             MotionToken::Down => self.get_buffer().map(|b| b.on_down_range()),
             MotionToken::Left => self.get_buffer().map(|b| b.on_left_range()),
             MotionToken::Right => self.get_buffer().map(|b| b.on_right_range()),
-            _ => None,
+            t => text_object(&t).and_then(|(object, inner)| {
+                self.get_mut_buffer()
+                    .and_then(|b| b.text_object_range(object, inner))
+            }),
         }
     }
 
     pub fn handle_motion_token(&mut self, token: MotionToken) {
+        if self.mode == Mode::Visual {
+            // `$` stretches a block to the end of every line until a sideways motion.
+            if let Some(buffer) = self.get_mut_buffer() {
+                buffer.block_to_eol = match token {
+                    MotionToken::Last => true,
+                    MotionToken::Up | MotionToken::Down => buffer.block_to_eol,
+                    _ => false,
+                }
+            }
+        }
         match token {
             MotionToken::Up => {
                 if let Some(buffer) = self.get_mut_buffer() {
@@ -781,7 +2935,7 @@ impl App {
             }
             MotionToken::Last => {
                 if let Some(buffer) = self.get_mut_buffer() {
-                    buffer.x_pos = sub_safe(buffer.current_line_len() as u16, 2);
+                    buffer.x_pos = sub_safe(buffer.current_line_len(), 2);
                     let change = WindowChange {
                         id: buffer.id,
                         x_pos: buffer.x_pos,
@@ -798,7 +2952,7 @@ impl App {
             }
             MotionToken::LastNonBlank => {
                 if let Some(buffer) = self.get_mut_buffer() {
-                    buffer.x_pos = sub_safe(buffer.current_line_len() as u16, 2);
+                    buffer.x_pos = sub_safe(buffer.current_line_len(), 2);
                     let change = WindowChange {
                         id: buffer.id,
                         x_pos: buffer.x_pos,
@@ -815,7 +2969,7 @@ impl App {
             }
             MotionToken::First => {
                 if let Some(buffer) = self.get_mut_buffer() {
-                    buffer.x_pos = 0 as u16;
+                    buffer.x_pos = 0;
                     let change = WindowChange {
                         id: buffer.id,
                         x_pos: buffer.x_pos,
@@ -866,7 +3020,7 @@ impl App {
             }
             MotionToken::FirstNonBlank => {
                 if let Some(buffer) = self.get_mut_buffer() {
-                    buffer.x_pos = 0 as u16;
+                    buffer.x_pos = 0;
                     let change = WindowChange {
                         id: buffer.id,
                         x_pos: buffer.x_pos,
@@ -915,6 +3069,122 @@ impl App {
 
                 self.render_ui();
             }
+            MotionToken::NextFunction | MotionToken::PrevFunction => {
+                let forward = token == MotionToken::NextFunction;
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.move_to_function(forward);
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+
+                self.render_ui();
+            }
+            MotionToken::WindowTop | MotionToken::WindowMiddle | MotionToken::WindowBottom => {
+                let scrolloff = self.options.scrolloff;
+                let line = match token {
+                    MotionToken::WindowTop => WindowLine::Top,
+                    MotionToken::WindowMiddle => WindowLine::Middle,
+                    _ => WindowLine::Bottom,
+                };
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.move_to_window_line(line, scrolloff);
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+
+                self.render_ui();
+            }
+            MotionToken::NextFold | MotionToken::PrevFold => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    buffer.update_folds();
+                    let line = if token == MotionToken::NextFold {
+                        buffer.folds.next_start(buffer.y_pos)
+                    } else {
+                        buffer.folds.prev_end(buffer.y_pos)
+                    };
+                    if let Some(line) = line {
+                        buffer.move_to_char(buffer.text.line_to_char(line));
+                    }
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+
+                self.render_ui();
+            }
+            MotionToken::ExpandSelection => {
+                if let Some(buffer) = self.get_mut_buffer() {
+                    if buffer.expand_selection() {
+                        let change = WindowChange {
+                            id: buffer.id,
+                            x_pos: buffer.x_pos,
+                            y_pos: buffer.y_pos,
+                            title: Some(buffer.title.clone()),
+                            page_size: buffer.page_size,
+                            current_page: buffer.current_page,
+                            ..WindowChange::default()
+                        };
+                        if let Some(window) = self.get_mut_window() {
+                            window.update(change);
+                        }
+                        self.mode = Mode::Visual;
+                    }
+                }
+
+                self.render_ui();
+            }
+            t if self.mode == Mode::Visual && text_object(&t).is_some() => {
+                let range = text_object(&t).and_then(|(object, inner)| {
+                    self.get_mut_buffer()
+                        .and_then(|b| b.text_object_range(object, inner))
+                });
+                if let (Some((start, end)), Some(buffer)) = (range, self.get_mut_buffer()) {
+                    buffer.select_range(start, end);
+                    let change = WindowChange {
+                        id: buffer.id,
+                        x_pos: buffer.x_pos,
+                        y_pos: buffer.y_pos,
+                        title: Some(buffer.title.clone()),
+                        page_size: buffer.page_size,
+                        current_page: buffer.current_page,
+                        ..WindowChange::default()
+                    };
+                    if let Some(window) = self.get_mut_window() {
+                        window.update(change);
+                    }
+                }
+
+                self.render_ui();
+            }
             MotionToken::EndWord => {
                 if let Some(buffer) = self.get_mut_buffer() {
                     buffer.x_pos = buffer.end_current_word();
@@ -950,6 +3220,9 @@ impl App {
         } else {
             None
         };
+        if let (Mode::Visual, Some(Token::Operator(o))) = (&self.mode, &operator) {
+            self.handle_operator_token(o.clone());
+        }
         for token in tokens {
             match token {
                 Token::Command(t) => self.handle_command_token(t),
@@ -957,6 +3230,7 @@ impl App {
                 Token::Normal(t) => self.handle_normal_token(t),
                 Token::Insert(t) => self.handle_insert_token(t),
                 Token::Operator(t) => self.handle_operator_token(t),
+                Token::Visual(t) => self.handle_visual_token(t),
                 Token::Display(t) => self.handle_display_token(t),
                 Token::Motion(t) if operator_idx.is_some() => {
                     if let (Some((start_range, end_range)), Some(Token::Operator(o))) =
@@ -971,3 +3245,26 @@ impl App {
         }
     }
 }
+
+/// What a case operator makes of `c`.
+fn change_case(token: &OperatorToken, c: char) -> String {
+    match token {
+        OperatorToken::Uppercase => c.to_uppercase().collect(),
+        OperatorToken::Lowercase => c.to_lowercase().collect(),
+        _ if c.is_uppercase() => c.to_lowercase().collect(),
+        _ => c.to_uppercase().collect(),
+    }
+}
+
+/// The syntax tree text object a motion selects, and whether it is the inner variant.
+fn text_object(token: &MotionToken) -> Option<(TextObject, bool)> {
+    match token {
+        MotionToken::InnerFunction => Some((TextObject::Function, true)),
+        MotionToken::AroundFunction => Some((TextObject::Function, false)),
+        MotionToken::InnerClass => Some((TextObject::Class, true)),
+        MotionToken::AroundClass => Some((TextObject::Class, false)),
+        MotionToken::InnerArgument => Some((TextObject::Argument, true)),
+        MotionToken::AroundArgument => Some((TextObject::Argument, false)),
+        _ => None,
+    }
+}
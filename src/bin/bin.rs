@@ -21,7 +21,14 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let mut parser = Parser::new();
     loop {
         if let Ok(true) = poll(Duration::from_millis(250)) {
-            if let Ok(Event::Key(event)) = read() {
+            let event = read();
+            if let Ok(Event::FocusGained) = event {
+                app.on_focus_gained();
+            } else if let Ok(Event::Resize(width, height)) = event {
+                app.on_resize(width, height);
+            } else if let Ok(Event::Mouse(event)) = event {
+                app.on_mouse(event);
+            } else if let Ok(Event::Key(event)) = event {
                 let tokens = parser.handle_event(UserInput { event }, &app.mode);
                 if !tokens.is_empty() {
                     app.handle_tokens(tokens);
@@ -31,6 +38,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
                 ()
             }
+        } else {
+            app.on_idle();
         }
     }
     Ok(())
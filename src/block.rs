@@ -0,0 +1,166 @@
+use unicode_width::UnicodeWidthChar;
+
+/// A blockwise visual selection: lines `first..=last` and the display columns
+/// `left..right` on each of them, or everything from `left` to each line's end with `to_eol`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub first: usize,
+    pub last: usize,
+    pub left: usize,
+    pub right: usize,
+    pub to_eol: bool,
+}
+
+/// What a block takes out of one line: chars `start..end` touch the block, and `before` and
+/// `after` columns of the tab or wide char at either end lie outside it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cut {
+    pub start: usize,
+    pub end: usize,
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Where text goes in at a display column: at char `idx`, after taking out the `remove`
+/// chars there and putting back `before` and `after` columns of spaces around the text. A
+/// line that ends short of the column has `short` set and `before` pads it out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Split {
+    pub idx: usize,
+    pub remove: usize,
+    pub before: usize,
+    pub after: usize,
+    pub short: bool,
+}
+
+/// Columns `c` takes up when it starts at column `col`: up to the next tabstop for a tab.
+pub fn char_width(c: char, col: usize, tabstop: usize) -> usize {
+    if c == '\t' {
+        let tabstop = tabstop.max(1);
+        tabstop - col % tabstop
+    } else {
+        c.width().unwrap_or(0)
+    }
+}
+
+/// The display column each char of `line` starts at, and the one after the last char.
+pub fn columns(line: &str, tabstop: usize) -> Vec<usize> {
+    let mut columns = vec![0];
+    for c in line.chars() {
+        let col = *columns.last().unwrap_or(&0);
+        columns.push(col + char_width(c, col, tabstop));
+    }
+    columns
+}
+
+/// The columns `left..right` the block between two corners covers, each corner being a line
+/// and the char index of the cursor or anchor on it. A corner past the end of its line still
+/// covers one column.
+pub fn corner_columns(corners: [(&str, usize); 2], tabstop: usize) -> (usize, usize) {
+    let spans = corners
+        .iter()
+        .map(|(line, idx)| {
+            let columns = columns(line, tabstop);
+            let start = columns[(*idx).min(columns.len() - 1)];
+            let end = columns.get(idx + 1).copied().unwrap_or(start + 1);
+            (start, end.max(start + 1))
+        })
+        .collect::<Vec<(usize, usize)>>();
+    (spans[0].0.min(spans[1].0), spans[0].1.max(spans[1].1))
+}
+
+/// The chars of `line` the columns `left..right` cover.
+pub fn cut(line: &str, left: usize, right: usize, tabstop: usize) -> Cut {
+    let columns = columns(line, tabstop);
+    let len = columns.len() - 1;
+    let start = (0..len).find(|i| columns[i + 1] > left).unwrap_or(len);
+    let end = (start..len)
+        .find(|i| columns[*i] >= right)
+        .unwrap_or(len)
+        .max(start);
+    Cut {
+        start,
+        end,
+        before: if start < end {
+            left.saturating_sub(columns[start])
+        } else {
+            0
+        },
+        after: columns[end].saturating_sub(right),
+    }
+}
+
+/// The text the columns `left..right` of `line` hold, with the part of a tab or wide char
+/// cut by an edge of the block turned into spaces.
+pub fn cut_text(line: &str, left: usize, right: usize, tabstop: usize) -> String {
+    let columns = columns(line, tabstop);
+    line.chars()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            let (start, end) = (columns[i], columns[i + 1]);
+            let covered = end.min(right).saturating_sub(start.max(left));
+            if covered == 0 {
+                None
+            } else if covered == end - start {
+                Some(c.to_string())
+            } else {
+                Some(" ".repeat(covered))
+            }
+        })
+        .collect()
+}
+
+/// Where text put in at display column `col` of `line` goes. A tab or wide char the column
+/// falls inside is split into spaces around the text.
+pub fn split(line: &str, col: usize, tabstop: usize) -> Split {
+    let columns = columns(line, tabstop);
+    let len = columns.len() - 1;
+    let width = columns[len];
+    if width < col {
+        return Split {
+            idx: len,
+            remove: 0,
+            before: col - width,
+            after: 0,
+            short: true,
+        };
+    }
+    match (0..len).find(|i| columns[i + 1] > col) {
+        Some(i) if columns[i] < col => Split {
+            idx: i,
+            remove: 1,
+            before: col - columns[i],
+            after: columns[i + 1] - col,
+            short: false,
+        },
+        Some(i) => Split {
+            idx: i,
+            remove: 0,
+            before: 0,
+            after: 0,
+            short: false,
+        },
+        None => Split {
+            idx: len,
+            remove: 0,
+            before: 0,
+            after: 0,
+            short: false,
+        },
+    }
+}
+
+/// `c` repeated to fill `width` columns, with spaces for what a wide `c` can't fill.
+pub fn fill(c: char, width: usize) -> String {
+    let char_width = c.width().unwrap_or(1).max(1);
+    c.to_string().repeat(width / char_width) + &" ".repeat(width % char_width)
+}
+
+/// The widest line of a yanked block, in columns.
+pub fn width(lines: &[String], tabstop: usize) -> usize {
+    lines
+        .iter()
+        .map(|line| columns(line, tabstop).last().copied().unwrap_or(0))
+        .max()
+        .unwrap_or(0)
+}
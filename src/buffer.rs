@@ -1,53 +1,285 @@
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+use crate::block::{self, Block, Cut};
+use crate::encoding::{self, Encoding, LineEnding};
+use crate::file::{self, DiskChange, DiskState};
+use crate::filetype;
+use crate::fold::Folds;
+use crate::options::{self, BufferOptions};
+use crate::swap::{self, SwapInfo};
+use crate::syntax_tree::{SyntaxTree, TextObject};
 use crate::{add_safe, mut_add_safe, mut_sub_safe, sub_safe};
+use anyhow::Result as AnyHowResult;
 use arboard::Clipboard;
 use ropey::Rope;
 use uuid::Uuid;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scroll {
+    LineDown,
+    LineUp,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    CursorTop,
+    CursorCenter,
+    CursorBottom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowLine {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// How a visual selection extends from its anchor to the cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectMode {
+    Char,
+    Line,
+    Block,
+}
+
 #[derive(Clone)]
 pub struct Buffer {
     pub id: Uuid,
+    pub number: usize,
     pub past_states: Vec<Rope>,
     pub future_states: Vec<Rope>,
     pub file_path: Option<String>,
     pub command_text: Option<String>,
-    pub x_pos: u16,
-    pub y_pos: u16,
+    pub x_pos: usize,
+    pub y_pos: usize,
     pub start_select_pos: Option<usize>,
     pub end_select_pos: Option<usize>,
+    pub select_mode: SelectMode,
+    pub block_to_eol: bool,
+    pub last_selection: Option<(usize, usize, SelectMode)>,
     pub clipboard: Arc<Mutex<Clipboard>>,
     pub text: Rope,
     pub title: String,
-    pub page_size: u16,
-    pub current_page: u16,
+    pub page_size: usize,
+    pub current_page: usize,
+    pub saved_state: Option<usize>,
+    pub scratch: bool,
+    pub changed_tick: usize,
+    pub swap_tick: usize,
+    pub swap_conflict: Option<SwapInfo>,
+    pub disk_state: Option<DiskState>,
+    pub large_file: bool,
+    pub encoding: Encoding,
+    pub bom: bool,
+    pub line_ending: LineEnding,
+    pub filetype: String,
+    pub local_options: BufferOptions,
+    pub syntax_tree: Option<SyntaxTree>,
+    pub folds: Folds,
 }
 
 impl Buffer {
-    pub fn on_save(&mut self) -> Result<(), std::io::Error> {
-        if let Some(file_path) = &self.file_path {
-            let file = if std::path::Path::new(&file_path).exists() {
-                std::fs::OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(&file_path)?
+    pub fn on_save(&mut self, backup: bool) -> Result<(usize, usize), std::io::Error> {
+        if let Some(file_path) = self.file_path.clone() {
+            let written = self.write_to_path(&file_path, false, backup)?;
+            self.mark_saved();
+            return Ok(written);
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "E32: No file name",
+        ))
+    }
+
+    pub fn write_to_path(
+        &self,
+        file_path: &str,
+        append: bool,
+        backup: bool,
+    ) -> Result<(usize, usize), std::io::Error> {
+        let path = std::path::Path::new(file_path);
+        let plain =
+            self.encoding == Encoding::Utf8 && !self.bom && self.line_ending == LineEnding::Lf;
+        let encoded = if plain {
+            None
+        } else {
+            Some(encoding::encode(
+                &self.text.to_string(),
+                self.encoding,
+                self.bom && !append,
+                self.line_ending,
+            )?)
+        };
+        let bytes_written = encoded
+            .as_ref()
+            .map_or(self.text.len_bytes(), |bytes| bytes.len());
+        let write = |writer: &mut dyn std::io::Write| -> Result<(), std::io::Error> {
+            match &encoded {
+                Some(bytes) => writer.write_all(bytes),
+                None => self.text.write_to(writer),
+            }
+        };
+        if append {
+            file::append_to(path, write)?;
+        } else {
+            file::write_atomic(path, backup, write)?;
+        }
+        Ok((self.line_count(), bytes_written))
+    }
+
+    pub fn set_option(&mut self, arg: &str) -> Option<AnyHowResult<()>> {
+        let (name, value) = options::split_option(arg);
+        if let ("filetype" | "ft", Some(value)) = (name, value) {
+            self.set_filetype(value);
+            return Some(Ok(()));
+        }
+        if let Some(result) = self.local_options.set(arg) {
+            self.refresh_syntax_tree();
+            return Some(result);
+        }
+        let result = match (name, value) {
+            ("fileencoding" | "fenc", Some(value)) => {
+                Encoding::from_name(value).map(|e| self.encoding = e)
+            }
+            ("fileformat" | "ff", Some(value)) => {
+                LineEnding::from_name(value).map(|l| self.line_ending = l)
+            }
+            ("bomb", None) => {
+                self.bom = true;
+                Ok(())
+            }
+            ("nobomb", None) => {
+                self.bom = false;
+                Ok(())
+            }
+            _ => return None,
+        };
+        if result.is_ok() {
+            self.saved_state = None;
+        }
+        Some(result)
+    }
+
+    pub fn set_filetype(&mut self, filetype: &str) {
+        self.filetype = filetype.to_owned();
+        self.local_options = filetype::default_options(filetype);
+        self.syntax_tree = None;
+        self.refresh_syntax_tree();
+    }
+
+    /// Creates or drops the parse tree to match the `treesitter` option. Large files are never
+    /// parsed, and filetypes without a compiled-in grammar fall back to syntect.
+    fn refresh_syntax_tree(&mut self) {
+        if !self.local_options.treesitter || self.large_file {
+            self.syntax_tree = None;
+        } else if self.syntax_tree.is_none() {
+            self.syntax_tree = SyntaxTree::new(&self.filetype);
+        }
+    }
+
+    pub fn sync_syntax_tree(&mut self) {
+        if let Some(tree) = self.syntax_tree.as_mut() {
+            tree.update(&self.text, self.changed_tick);
+        }
+    }
+
+    /// Brings folds up to date with the text and `foldmethod`. The syntax method folds on the
+    /// parse tree, so it finds nothing where tree-sitter is off.
+    pub fn update_folds(&mut self) {
+        self.sync_syntax_tree();
+        self.folds.update(
+            self.local_options.foldmethod,
+            &self.text,
+            self.local_options.shiftwidth,
+            self.syntax_tree.as_ref(),
+            self.changed_tick,
+        );
+    }
+
+    /// Moves the cursor onto the first line of the closed fold it is hidden in, if any.
+    pub fn leave_closed_fold(&mut self) {
+        if let Some((start, _)) = self.folds.closed_fold_at(self.y_pos) {
+            self.y_pos = start;
+            self.x_pos = 0;
+            if start < self.current_page {
+                self.current_page = start;
+            }
+        }
+    }
+
+    /// Picks the filetype from the file name and contents, then applies any modeline options
+    /// on top of that filetype's defaults.
+    pub fn detect_filetype(&mut self) {
+        let line_count = self.text.len_lines();
+        let edge_lines = (0..line_count.min(filetype::MODELINE_SCAN))
+            .chain(
+                line_count
+                    .saturating_sub(filetype::MODELINE_SCAN)
+                    .max(filetype::MODELINE_SCAN)..line_count,
+            )
+            .map(|idx| self.text.line(idx).to_string().trim_end().to_owned())
+            .collect::<Vec<String>>();
+        let filetype = filetype::detect(self.file_path.as_deref(), &edge_lines);
+        self.set_filetype(&filetype);
+        for option in filetype::modeline_options(&edge_lines) {
+            if !matches!(options::split_option(&option).0, "filetype" | "ft") {
+                let _ = self.local_options.set(&option);
+            }
+        }
+        self.refresh_syntax_tree();
+    }
+
+    pub fn file_info(&self) -> String {
+        format!(
+            "{}{}{} {}{}",
+            if self.filetype.is_empty() {
+                String::new()
             } else {
-                std::fs::File::create(&file_path)?
-            };
-            self.text.write_to(std::io::BufWriter::new(file))?;
-            return Ok(());
+                format!("{} ", self.filetype)
+            },
+            self.encoding.name(),
+            if self.bom { " [BOM]" } else { "" },
+            self.line_ending.name(),
+            if self.large_file { " [large]" } else { "" }
+        )
+    }
+
+    pub fn line_count(&self) -> usize {
+        let len_lines = self.text.len_lines();
+        if self.text.len_chars() > 0 && self.text.char(self.text.len_chars() - 1) == '\n' {
+            len_lines - 1
+        } else if self.text.len_chars() == 0 {
+            0
+        } else {
+            len_lines
+        }
+    }
+
+    pub fn reload(&mut self) -> Result<(), std::io::Error> {
+        if let Some(file_path) = &self.file_path {
+            let (text, encoding, bom, line_ending) = encoding::decode(&std::fs::read(file_path)?);
+            self.text = Rope::from_str(&text);
+            self.encoding = encoding;
+            self.bom = bom;
+            self.line_ending = line_ending;
+            self.past_states = vec![];
+            self.future_states = vec![];
+            self.changed_tick += 1;
+            self.mark_saved();
+            self.start_select_pos = None;
+            self.end_select_pos = None;
+            if self.y_pos >= self.text.len_lines() {
+                self.move_to_last_line();
+            }
+            self.x_pos = self.x_pos.min(sub_safe(self.current_line_len(), 1));
         }
         Ok(())
     }
 
+    /// Moves up one line, treating a closed fold as a single line.
     pub fn on_up(&mut self) {
-        if self.y_pos > 0 {
-            mut_sub_safe(&mut self.y_pos, 1);
-        }
-        if self.y_pos != 0 && (self.y_pos % self.page_size) == 0 {
-            mut_sub_safe(&mut self.current_page, self.page_size);
-        }
+        self.y_pos = self.rows_up(self.y_pos, 1);
     }
 
     pub fn on_up_range(&self) -> (usize, usize) {
@@ -55,37 +287,174 @@ impl Buffer {
         if self.y_pos > 0 {
             mut_sub_safe(&mut new_y_pos, 1);
         }
-        let line_length = self.text.line(self.y_pos as usize).len_chars();
+        let line_length = self.text.line(self.y_pos).len_chars();
         (
-            self.text.line_to_char(new_y_pos as usize),
-            (self.text.line_to_char(self.y_pos as usize) + line_length),
+            self.text.line_to_char(new_y_pos),
+            (self.text.line_to_char(self.y_pos) + line_length),
         )
     }
 
+    /// Moves down one line, treating a closed fold as a single line.
     pub fn on_down(&mut self) {
-        if self.y_pos <= sub_safe(self.text.len_lines() as u16, 1) {
-            mut_add_safe(&mut self.y_pos, 1);
+        self.y_pos = self.rows_down(self.y_pos, 1);
+    }
+
+    /// The line `rows` screen rows below `line`, stopping at the last line.
+    pub fn rows_down(&self, line: usize, rows: usize) -> usize {
+        let last_line = sub_safe(self.text.len_lines(), 1);
+        let mut line = line.min(last_line);
+        for _ in 0..rows {
+            let next = self.folds.line_below(line);
+            if next > last_line {
+                break;
+            }
+            line = next;
+        }
+        line
+    }
+
+    /// The line `rows` screen rows above `line`, stopping at the first line.
+    pub fn rows_up(&self, line: usize, rows: usize) -> usize {
+        let mut line = self
+            .folds
+            .closed_fold_at(line)
+            .map(|(start, _)| start)
+            .unwrap_or(line);
+        for _ in 0..rows {
+            if line == 0 {
+                break;
+            }
+            line = self.folds.line_above(line);
         }
-        if self.y_pos != 0 && (self.y_pos % self.page_size) == 0 {
-            mut_add_safe(&mut self.current_page, self.page_size);
+        line
+    }
+
+    /// Scrolls the least amount that keeps `scrolloff` lines of context above and below the
+    /// cursor, or centres it when the window is too short for that.
+    pub fn scroll_to_cursor(&mut self, scrolloff: usize) {
+        let height = self.page_size.max(1);
+        let scrolloff = scrolloff.min(sub_safe(height, 1) / 2);
+        self.y_pos = self.y_pos.min(sub_safe(self.text.len_lines(), 1));
+        let highest_top = self.rows_up(self.y_pos, scrolloff);
+        let lowest_top = self.rows_up(self.y_pos, sub_safe(sub_safe(height, 1), scrolloff));
+        if self.current_page > highest_top {
+            self.current_page = highest_top;
+        } else if self.current_page < lowest_top {
+            self.current_page = lowest_top;
+        }
+        self.current_page = self.rows_up(self.current_page, 0);
+    }
+
+    /// Moves the cursor to what is shown `row` screen rows below the top of the viewport and
+    /// `col` chars into the line, as far as the text goes.
+    pub fn move_to_screen(&mut self, row: usize, col: usize) {
+        self.y_pos = self.rows_down(self.current_page, row);
+        self.x_pos = col;
+        self.clamp_x_pos();
+    }
+
+    fn clamp_x_pos(&mut self) {
+        self.x_pos = self.x_pos.min(sub_safe(self.current_line_len(), 1));
+    }
+
+    pub fn scroll(&mut self, scroll: Scroll, scrolloff: usize) {
+        let height = self.page_size.max(1);
+        let scrolloff = scrolloff.min(sub_safe(height, 1) / 2);
+        let last_line = sub_safe(self.text.len_lines(), 1);
+        match scroll {
+            Scroll::LineDown => {
+                self.current_page = self.rows_down(self.current_page, 1);
+                let top = self.rows_down(self.current_page, scrolloff);
+                if self.y_pos < top && self.current_page > 0 {
+                    self.y_pos = top;
+                }
+            }
+            Scroll::LineUp => {
+                self.current_page = self.rows_up(self.current_page, 1);
+                let bottom = self.rows_down(self.current_page, sub_safe(height, 1));
+                let limit = if bottom == last_line {
+                    bottom
+                } else {
+                    self.rows_up(bottom, scrolloff)
+                };
+                if self.y_pos > limit {
+                    self.y_pos = limit;
+                }
+            }
+            Scroll::HalfPageDown | Scroll::HalfPageUp => {
+                let rows = (height / 2).max(1);
+                if scroll == Scroll::HalfPageDown {
+                    self.current_page = self.rows_down(self.current_page, rows).min(
+                        self.rows_up(last_line, sub_safe(height, 1))
+                            .max(self.current_page),
+                    );
+                    self.y_pos = self.rows_down(self.y_pos, rows);
+                } else {
+                    self.current_page = self.rows_up(self.current_page, rows);
+                    self.y_pos = self.rows_up(self.y_pos, rows);
+                }
+            }
+            Scroll::PageDown => {
+                self.current_page = self.rows_down(self.current_page, sub_safe(height, 2).max(1));
+                self.y_pos = self.y_pos.max(self.rows_down(self.current_page, scrolloff));
+            }
+            Scroll::PageUp => {
+                self.current_page = self.rows_up(self.current_page, sub_safe(height, 2).max(1));
+                let bottom = self.rows_down(self.current_page, sub_safe(height, 1));
+                self.y_pos = self.y_pos.min(self.rows_up(bottom, scrolloff));
+            }
+            Scroll::CursorTop => self.current_page = self.rows_up(self.y_pos, scrolloff),
+            Scroll::CursorCenter => {
+                self.current_page = self.rows_up(self.y_pos, sub_safe(height, 1) / 2)
+            }
+            Scroll::CursorBottom => {
+                self.current_page =
+                    self.rows_up(self.y_pos, sub_safe(sub_safe(height, 1), scrolloff))
+            }
         }
+        self.clamp_x_pos();
+    }
+
+    /// Moves the cursor to the top, middle or bottom line of the window for `H`, `M` and `L`,
+    /// keeping `scrolloff` lines away from the edges unless the file ends there.
+    pub fn move_to_window_line(&mut self, line: WindowLine, scrolloff: usize) {
+        let height = self.page_size.max(1);
+        let scrolloff = scrolloff.min(sub_safe(height, 1) / 2);
+        let top = self.current_page;
+        let bottom = self.rows_down(top, sub_safe(height, 1));
+        let visible = (top..=bottom)
+            .filter(|line| {
+                self.folds
+                    .closed_fold_at(*line)
+                    .map(|(s, _)| s == *line)
+                    .unwrap_or(true)
+            })
+            .count();
+        self.y_pos = match line {
+            WindowLine::Top if top == 0 => 0,
+            WindowLine::Top => self.rows_down(top, scrolloff),
+            WindowLine::Middle => self.rows_down(top, sub_safe(visible, 1) / 2),
+            WindowLine::Bottom if bottom == sub_safe(self.text.len_lines(), 1) => bottom,
+            WindowLine::Bottom => self.rows_up(bottom, scrolloff),
+        };
+        self.x_pos = 0;
     }
 
     pub fn on_down_range(&self) -> (usize, usize) {
         let mut new_y_pos = self.y_pos;
 
-        if self.y_pos <= sub_safe(self.text.len_lines() as u16, 1) {
+        if self.y_pos <= sub_safe(self.text.len_lines(), 1) {
             mut_add_safe(&mut new_y_pos, 1);
         }
         (
-            self.text.line_to_char(new_y_pos as usize),
-            (self.text.line_to_char(self.y_pos as usize)),
+            self.text.line_to_char(new_y_pos),
+            (self.text.line_to_char(self.y_pos)),
         )
     }
 
     pub fn current_line_len(&self) -> usize {
         self.text
-            .get_line(self.y_pos as usize)
+            .get_line(self.y_pos)
             .map(|l| l.len_chars())
             .unwrap_or_default()
     }
@@ -95,19 +464,19 @@ impl Buffer {
     }
 
     pub fn start_of_current_line(&self) -> usize {
-        self.text.line_to_char(self.y_pos as usize)
+        self.text.line_to_char(self.y_pos)
     }
 
     pub fn current_line_chars(&self) -> Vec<char> {
         self.text
-            .line(self.y_pos as usize)
+            .line(self.y_pos)
             .chars()
             .filter(|c| c != &'\n')
             .collect()
     }
 
     pub fn on_right(&mut self) {
-        let chars: u16 = self.current_line_len() as u16;
+        let chars = self.current_line_len();
         if self.x_pos < sub_safe(chars, 1) {
             mut_add_safe(&mut self.x_pos, 1);
         } else {
@@ -117,14 +486,14 @@ impl Buffer {
 
     pub fn on_right_range(&self) -> (usize, usize) {
         let mut new_x_pos = self.x_pos;
-        let chars: u16 = self.current_line_len() as u16;
+        let chars = self.current_line_len();
 
         if self.x_pos < sub_safe(chars, 1) {
             mut_add_safe(&mut new_x_pos, 1);
         }
         (
-            self.text.line_to_char(new_x_pos as usize),
-            (self.text.line_to_char(self.x_pos as usize)),
+            self.text.line_to_char(new_x_pos),
+            (self.text.line_to_char(self.x_pos)),
         )
     }
 
@@ -141,66 +510,389 @@ impl Buffer {
 
     pub fn on_left_range(&self) -> (usize, usize) {
         let mut new_x_pos = self.x_pos;
-        let chars: u16 = self.current_line_len() as u16;
+        let chars = self.current_line_len();
 
         if self.x_pos < sub_safe(chars, 1) {
             mut_sub_safe(&mut new_x_pos, 1);
         }
         (
-            self.text.line_to_char(new_x_pos as usize),
-            (self.text.line_to_char(self.x_pos as usize)),
+            self.text.line_to_char(new_x_pos),
+            (self.text.line_to_char(self.x_pos)),
         )
     }
 
     pub fn recenter(&mut self) {
-        if self.y_pos <= 0_u16 {
+        if self.y_pos == 0 {
             self.on_down()
         }
-        if self.y_pos < self.text.len_lines() as u16 {
+        if self.y_pos < self.text.len_lines() {
             self.on_up()
         }
     }
 
     pub fn get_cursor_idx(&self) -> usize {
-        self.text.line_to_char(self.y_pos as usize) + self.x_pos as usize
+        self.text.line_to_char(self.y_pos) + self.x_pos
     }
 
     pub fn remove_char(&mut self) {
         let end_idx = self.get_cursor_idx();
         if end_idx > 0 {
-            let start_idx = sub_safe(end_idx as u16, 1) as usize;
-            self.future_states = vec![];
-            self.past_states.push(self.text.clone());
+            let start_idx = sub_safe(end_idx, 1);
+            self.set_states();
             let _ = self.text.try_remove(start_idx..end_idx);
             self.on_left()
         }
     }
 
-    /*
+    /// The chars the visual selection covers: from its anchor to the cursor, both included,
+    /// or every line between them for a linewise or blockwise selection.
     pub fn get_selected_range(&self) -> Option<(usize, usize)> {
-        let offset;
-        let end_idx;
-        if let Some(end) = self.end_select_pos {
-            offset = 0;
-            end_idx = end;
+        let anchor = self.start_select_pos?;
+        let cursor = self.get_cursor_idx();
+        let len_chars = self.text.len_chars();
+        let start = anchor.min(cursor).min(len_chars);
+        let end = anchor.max(cursor).min(len_chars);
+        match self.select_mode {
+            SelectMode::Char => Some((start, add_safe(end, 1).min(len_chars))),
+            SelectMode::Line | SelectMode::Block => {
+                let first_line = self.text.char_to_line(start);
+                let last_line = self.text.char_to_line(end);
+                Some((
+                    self.text.line_to_char(first_line),
+                    self.text
+                        .line_to_char(add_safe(last_line, 1).min(self.text.len_lines())),
+                ))
+            }
+        }
+    }
+
+    /// `o` in visual mode: the cursor jumps to the anchor, which stays where the cursor was.
+    pub fn swap_selection_ends(&mut self) {
+        if let Some(anchor) = self.start_select_pos {
+            self.start_select_pos = Some(self.get_cursor_idx());
+            self.move_to_char(anchor);
+        }
+    }
+
+    /// Drops the selection, remembering it for `gv`.
+    pub fn end_selection(&mut self) {
+        if let Some(anchor) = self.start_select_pos {
+            self.last_selection = Some((anchor, self.get_cursor_idx(), self.select_mode));
+        }
+        self.start_select_pos = None;
+        self.end_select_pos = None;
+    }
+
+    /// `gv`: selects what the last visual selection covered, as far as the text still goes.
+    pub fn reselect(&mut self) -> bool {
+        match self.last_selection {
+            Some((anchor, cursor, select_mode)) => {
+                let len_chars = self.text.len_chars();
+                self.start_select_pos = Some(anchor.min(len_chars));
+                self.end_select_pos = None;
+                self.select_mode = select_mode;
+                self.move_to_char(cursor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces each char in `start..end` with what `f` makes of it, for the case operators.
+    pub fn replace_chars(&mut self, start: usize, end: usize, f: impl Fn(char) -> String) {
+        if start >= end.min(self.text.len_chars()) {
+            return;
+        }
+        self.set_states();
+        self.map_chars(start, end, &f);
+    }
+
+    fn map_chars(&mut self, start: usize, end: usize, f: &impl Fn(char) -> String) {
+        let end = end.min(self.text.len_chars());
+        if start >= end {
+            return;
+        }
+        let replaced = self
+            .text
+            .slice(start..end)
+            .chars()
+            .map(f)
+            .collect::<String>();
+        self.text.remove(start..end);
+        self.text.insert(start, &replaced);
+    }
+
+    /// `line` without its line break.
+    fn line_content(&self, line: usize) -> String {
+        self.text
+            .get_line(line)
+            .map(|l| l.chars().collect::<String>())
+            .unwrap_or_default()
+            .trim_end_matches(&['\r', '\n'][..])
+            .to_string()
+    }
+
+    /// The rectangle a blockwise selection covers, with the anchor and the cursor at
+    /// opposite corners.
+    pub fn selected_block(&self) -> Option<Block> {
+        let anchor = self.start_select_pos?.min(self.text.len_chars());
+        let anchor_line = self.text.char_to_line(anchor);
+        let anchor_x = anchor - self.text.line_to_char(anchor_line);
+        let (left, right) = block::corner_columns(
+            [
+                (&self.line_content(anchor_line), anchor_x),
+                (&self.line_content(self.y_pos), self.x_pos),
+            ],
+            self.local_options.tabstop,
+        );
+        Some(Block {
+            first: anchor_line.min(self.y_pos),
+            last: anchor_line.max(self.y_pos),
+            left,
+            right,
+            to_eol: self.block_to_eol,
+        })
+    }
+
+    fn cut_line(&self, block: &Block, line: usize) -> Cut {
+        let right = if block.to_eol {
+            usize::MAX
+        } else {
+            block.right
+        };
+        block::cut(
+            &self.line_content(line),
+            block.left,
+            right,
+            self.local_options.tabstop,
+        )
+    }
+
+    /// The chars of each line of `block`, as ranges of the whole text.
+    pub fn block_ranges(&self, block: &Block) -> Vec<(usize, usize)> {
+        (block.first..=block.last)
+            .map(|line| {
+                let cut = self.cut_line(block, line);
+                let line_start = self.text.line_to_char(line);
+                (line_start + cut.start, line_start + cut.end)
+            })
+            .collect()
+    }
+
+    /// Puts the text of `block` on the clipboard, a line of text for each line of the block,
+    /// and returns those lines.
+    pub fn yank_block(&self, block: &Block) -> Vec<String> {
+        let right = if block.to_eol {
+            usize::MAX
         } else {
-            offset = 1;
-            end_idx = self.get_cursor_idx();
+            block.right
+        };
+        let lines = (block.first..=block.last)
+            .map(|line| {
+                block::cut_text(
+                    &self.line_content(line),
+                    block.left,
+                    right,
+                    self.local_options.tabstop,
+                )
+            })
+            .collect::<Vec<String>>();
+        if let Ok(mut clipboard) = self.clipboard.lock() {
+            clipboard
+                .set_text(lines.join("\n"))
+                .expect("Could not set value to system clipboard");
         }
-        if let Some(start_idx) = self.start_select_pos {
-            if start_idx > end_idx {
-                return Some((end_idx, start_idx + offset));
+        lines
+    }
+
+    /// Takes the chars of each line of `block` out, leaving spaces for the part of a tab or
+    /// wide char outside it, with `f` giving what goes in their place from the columns taken.
+    fn replace_block(&mut self, block: &Block, f: impl Fn(usize) -> String) {
+        let tabstop = self.local_options.tabstop;
+        self.set_states();
+        for line in (block.first..=block.last).rev() {
+            let cut = self.cut_line(block, line);
+            if cut.start == cut.end {
+                continue;
+            }
+            let columns = block::columns(&self.line_content(line), tabstop);
+            let taken = columns[cut.end] - columns[cut.start] - cut.before - cut.after;
+            let line_start = self.text.line_to_char(line);
+            self.text
+                .remove(line_start + cut.start..line_start + cut.end);
+            self.text.insert(
+                line_start + cut.start,
+                &(" ".repeat(cut.before) + &f(taken) + &" ".repeat(cut.after)),
+            );
+        }
+        self.move_to_column(block.first, block.left);
+    }
+
+    /// Deletes `block`, leaving the cursor at its top left corner.
+    pub fn delete_block(&mut self, block: &Block) {
+        self.replace_block(block, |_| String::new());
+    }
+
+    /// `r{char}` on a block: every column of it becomes `c`.
+    pub fn fill_block(&mut self, block: &Block, c: char) {
+        self.replace_block(block, |taken| block::fill(c, taken));
+    }
+
+    /// Replaces each char of `block` with what `f` makes of it, for the case operators.
+    pub fn replace_block_chars(&mut self, block: &Block, f: impl Fn(char) -> String) {
+        self.set_states();
+        for (start, end) in self.block_ranges(block).into_iter().rev() {
+            self.map_chars(start, end, &f);
+        }
+        self.move_to_column(block.first, block.left);
+    }
+
+    /// Puts the cursor on the char at display column `col` of `line`.
+    pub fn move_to_column(&mut self, line: usize, col: usize) {
+        let content = self.line_content(line);
+        let split = block::split(&content, col, self.local_options.tabstop);
+        self.y_pos = line;
+        self.x_pos = split.idx.min(sub_safe(content.chars().count(), 1));
+    }
+
+    /// Inserts `text` at display column `col` of `line`, or at the end of the line without a
+    /// column, splitting a tab the column falls inside. A line that ends short of the column
+    /// is padded out to it with `pad` and left alone otherwise. Returns where the text went.
+    pub fn insert_at_column(
+        &mut self,
+        line: usize,
+        col: Option<usize>,
+        pad: bool,
+        text: &str,
+    ) -> Option<usize> {
+        let tabstop = self.local_options.tabstop;
+        let content = self.line_content(line);
+        let col = col.unwrap_or_else(|| block::width(&[content.clone()], tabstop));
+        let split = block::split(&content, col, tabstop);
+        if split.short && !pad {
+            return None;
+        }
+        let idx = self.text.line_to_char(line) + split.idx;
+        self.text.remove(idx..idx + split.remove);
+        self.text.insert(
+            idx,
+            &(" ".repeat(split.before) + text + &" ".repeat(split.after)),
+        );
+        Some(idx + split.before)
+    }
+
+    /// `p` with a yanked block: puts its lines in after the cursor on this line and the ones
+    /// below, padding each out to the block's width where more text follows it.
+    pub fn paste_block(&mut self, lines: &[String]) {
+        let tabstop = self.local_options.tabstop;
+        let columns = block::columns(&self.line_content(self.y_pos), tabstop);
+        let col = columns
+            .get(self.x_pos + 1)
+            .or(columns.last())
+            .copied()
+            .unwrap_or(0);
+        let width = block::width(lines, tabstop);
+        self.set_states();
+        let mut first_idx = None;
+        for (i, text) in lines.iter().enumerate() {
+            let line = self.y_pos + i;
+            if line >= self.text.len_lines() {
+                self.text.insert_char(self.text.len_chars(), '\n');
+            }
+            let content = self.line_content(line);
+            let split = block::split(&content, col, tabstop);
+            let text = if split.idx + split.remove < content.chars().count() {
+                let text_width = block::width(&[text.clone()], tabstop);
+                text.clone() + &" ".repeat(width - text_width)
             } else {
-                return Some((start_idx, end_idx + offset));
+                text.clone()
+            };
+            let idx = self.insert_at_column(line, Some(col), true, &text);
+            first_idx = first_idx.or(idx);
+        }
+        if let Some(idx) = first_idx {
+            self.x_pos = idx - self.text.line_to_char(self.y_pos);
+        }
+    }
+
+    /// Shifts lines `first..=last` one `shiftwidth` right, or left with `outdent`. Empty
+    /// lines are left alone.
+    pub fn indent_lines(&mut self, first: usize, last: usize, outdent: bool) {
+        let width = if self.local_options.shiftwidth == 0 {
+            self.local_options.tabstop.max(1)
+        } else {
+            self.local_options.shiftwidth
+        };
+        let tabstop = self.local_options.tabstop.max(1);
+        self.set_states();
+        for line in first..=last.min(sub_safe(self.text.len_lines(), 1)) {
+            let start = self.text.line_to_char(line);
+            let chars = self.text.line(line).chars().collect::<Vec<char>>();
+            let indent = chars
+                .iter()
+                .take_while(|c| **c == ' ' || **c == '\t')
+                .count();
+            if indent == chars.len() || chars[indent] == '\n' {
+                continue;
             }
+            let columns = chars[..indent].iter().fold(0, |col, c| {
+                if *c == '\t' {
+                    col - col % tabstop + tabstop
+                } else {
+                    col + 1
+                }
+            });
+            let columns = if outdent {
+                sub_safe(columns, width) / width * width
+            } else {
+                (columns / width + 1) * width
+            };
+            let new_indent = if self.local_options.expandtab {
+                " ".repeat(columns)
+            } else {
+                "\t".repeat(columns / tabstop) + &" ".repeat(columns % tabstop)
+            };
+            self.text.remove(start..start + indent);
+            self.text.insert(start, &new_indent);
         }
-        None
     }
-    */
 
-    pub fn end_current_word(&self) -> u16 {
+    /// `J`: joins lines `first..=last` into one, each line break and the indent after it
+    /// becoming a single space, or nothing before an empty line.
+    pub fn join_lines(&mut self, first: usize, last: usize) {
+        let last = last.min(sub_safe(self.text.len_lines(), 1));
+        if first >= last {
+            return;
+        }
+        self.set_states();
+        for _ in first..last {
+            let line_end = sub_safe(self.text.line_to_char(add_safe(first, 1)), 1);
+            let next = self
+                .text
+                .slice(add_safe(line_end, 1)..)
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .count();
+            let join_at = line_end;
+            self.text
+                .remove(join_at..add_safe(add_safe(join_at, 1), next));
+            let at_end = self
+                .text
+                .get_char(join_at)
+                .map(|c| c == '\n')
+                .unwrap_or(true);
+            let after_space = join_at > 0 && self.text.char(join_at - 1) == ' ';
+            if !at_end && !after_space {
+                self.text.insert_char(join_at, ' ');
+            }
+            self.x_pos = join_at - self.text.line_to_char(first);
+        }
+        self.y_pos = first;
+    }
+
+    pub fn end_current_word(&self) -> usize {
         let line_chars = self.current_line_chars();
-        let mut chars_cursor = line_chars[self.x_pos as usize..].iter();
+        let mut chars_cursor = line_chars[self.x_pos..].iter();
         let mut end_current_word = self.x_pos.clone();
 
         while let Some(c) = chars_cursor.next() {
@@ -213,12 +905,12 @@ impl Buffer {
         end_current_word
     }
 
-    pub fn find_next_word(&self) -> u16 {
+    pub fn find_next_word(&self) -> usize {
         let line_chars = self.current_line_chars();
         let end_current_word = self.end_current_word();
 
         if end_current_word != self.x_pos {
-            let mut chars_end_word = line_chars[end_current_word as usize..].iter();
+            let mut chars_end_word = line_chars[end_current_word..].iter();
             let mut start_next_word = end_current_word.clone();
             while let Some(c) = chars_end_word.next() {
                 if c.is_alphabetic() {
@@ -234,9 +926,9 @@ impl Buffer {
         }
     }
 
-    pub fn find_last_word(&self) -> u16 {
+    pub fn find_last_word(&self) -> usize {
         let line_chars = self.current_line_chars();
-        let mut chars_cursor = line_chars[..=self.x_pos as usize].iter().rev();
+        let mut chars_cursor = line_chars[..=self.x_pos].iter().rev();
         let mut start_current_word = self.x_pos.clone();
 
         while let Some(c) = chars_cursor.next() {
@@ -248,7 +940,7 @@ impl Buffer {
         }
 
         if start_current_word != self.x_pos {
-            let mut chars_end_word = line_chars[..=start_current_word as usize].iter().rev();
+            let mut chars_end_word = line_chars[..=start_current_word].iter().rev();
             let mut start_last_word = start_current_word.clone();
             while let Some(c) = chars_end_word.next() {
                 if c.is_alphabetic() {
@@ -271,7 +963,7 @@ impl Buffer {
     pub fn yank_line_range(&self, start_idx: usize, end_idx: usize) {
         if let (Ok(mut clipboard), Some(rope_slice)) = (
             self.clipboard.lock(),
-            self.text.get_slice(start_idx..end_idx as usize),
+            self.text.get_slice(start_idx..end_idx),
         ) {
             clipboard
                 .set_text(rope_slice)
@@ -282,7 +974,7 @@ impl Buffer {
     pub fn yank_lines(&self, start_idx: usize, end_idx: usize) {
         //account for the chance that the range is reversed
         let mut str_vec = vec![];
-        let line_count = self.text.len_lines() as usize;
+        let line_count = self.text.len_lines();
         if start_idx < 1 || end_idx < 1 {
             return ();
         }
@@ -292,11 +984,11 @@ impl Buffer {
         }
 
         if end_idx < start_idx {
-            for line in sub_safe(end_idx as u16, 1) as usize..start_idx {
+            for line in sub_safe(end_idx, 1)..start_idx {
                 str_vec.push(self.text.line(line).to_string())
             }
         } else {
-            for line in sub_safe(start_idx as u16, 1) as usize..end_idx {
+            for line in sub_safe(start_idx, 1)..end_idx {
                 str_vec.push(self.text.line(line).to_string())
             }
         }
@@ -309,7 +1001,7 @@ impl Buffer {
     }
 
     pub fn move_to_last_line(&mut self) {
-        let line_count = self.text.len_lines() as u16;
+        let line_count = self.text.len_lines();
         self.x_pos = 0;
         self.y_pos = sub_safe(line_count, 1);
         if line_count > self.page_size {
@@ -326,16 +1018,13 @@ impl Buffer {
     }
 
     pub fn move_to_line_number(&mut self, line_number: usize) {
-        let line_count = self.text.len_lines() as usize;
+        let line_count = self.text.len_lines();
         if line_number < line_count {
-            self.y_pos = sub_safe(line_number as u16, 1);
+            self.y_pos = sub_safe(line_number, 1);
             self.x_pos = 0;
-            if line_number >= self.page_size as usize {
+            if line_number >= self.page_size {
                 self.current_page = sub_safe(
-                    sub_safe(
-                        line_number as u16,
-                        (line_number % self.page_size as usize) as u16,
-                    ),
+                    sub_safe(line_number, line_number % self.page_size),
                     self.page_size,
                 )
             } else {
@@ -344,16 +1033,9 @@ impl Buffer {
         }
     }
 
-    pub fn select_line(&mut self) {
-        let idx = self.get_cursor_idx();
-        self.start_select_pos = Some(idx);
-        self.end_select_pos = Some(self.end_of_current_line());
-    }
-
     pub fn add_newline_below(&mut self) {
         let char_idx = self.end_of_current_line();
-        self.past_states.push(self.text.clone());
-        self.future_states = vec![];
+        self.set_states();
         self.x_pos = 0;
         mut_add_safe(&mut self.y_pos, 1);
         let _ = self.text.try_insert_char(char_idx, '\n');
@@ -363,31 +1045,36 @@ impl Buffer {
         if let Some(past_state) = self.past_states.pop() {
             self.future_states.push(self.text.clone());
             self.text = past_state;
+            self.changed_tick += 1;
             self.recenter();
         }
     }
 
     pub fn redo(&mut self) {
         if let Some(future_state) = self.future_states.pop() {
+            self.past_states.push(self.text.clone());
             self.text = future_state;
+            self.changed_tick += 1;
         }
     }
 
+    pub fn clipboard_text(&self) -> Option<String> {
+        self.clipboard
+            .lock()
+            .ok()
+            .and_then(|mut clipboard| clipboard.get_text().ok())
+    }
+
     pub fn paste_text(&mut self) {
-        if let Ok(mut clipboard) = self.clipboard.lock() {
-            let coppied_text = clipboard
-                .get_text()
-                .expect("Could not set value to system clipboard");
+        if let Some(coppied_text) = self.clipboard_text() {
             let char_idx = self.get_cursor_idx();
-            self.past_states.push(self.text.clone());
-            self.future_states = vec![];
+            self.set_states();
             let _ = self.text.try_insert(char_idx, &coppied_text);
         }
     }
 
     pub fn delete_line(&mut self) {
-        self.future_states = vec![];
-        self.past_states.push(self.text.clone());
+        self.set_states();
         let _ = self
             .text
             .try_remove(self.start_of_current_line()..self.end_of_current_line());
@@ -395,16 +1082,44 @@ impl Buffer {
     }
 
     pub fn delete_line_range(&mut self, start_idx: usize, end_idx: usize) {
-        self.future_states = vec![];
-        self.past_states.push(self.text.clone());
+        self.set_states();
 
         let _ = self.text.try_remove(start_idx..end_idx);
         self.recenter();
     }
 
     pub fn set_states(&mut self) {
+        if self
+            .saved_state
+            .map(|depth| depth > self.past_states.len())
+            .unwrap_or(false)
+        {
+            self.saved_state = None;
+        }
         self.future_states = vec![];
         self.past_states.push(self.text.clone());
+        self.changed_tick += 1;
+    }
+
+    /// Drops the undo states pushed after the first one past `depth`, so a single `u` undoes
+    /// everything changed since then.
+    pub fn join_states_since(&mut self, depth: usize) {
+        if self.past_states.len() > depth + 1 {
+            self.past_states.truncate(depth + 1);
+            if self.saved_state.map(|saved| saved > depth).unwrap_or(false) {
+                self.saved_state = None;
+            }
+        }
+        self.changed_tick += 1;
+    }
+
+    pub fn is_modified(&self) -> bool {
+        !self.scratch && self.saved_state != Some(self.past_states.len())
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_state = Some(self.past_states.len());
+        self.record_disk_state();
     }
 
     pub fn delete_line_direct(&mut self) {
@@ -416,31 +1131,26 @@ impl Buffer {
 
     pub fn insert_return(&mut self) {
         let char_idx = self.get_cursor_idx();
-        self.past_states.push(self.text.clone());
-        self.future_states = vec![];
+        self.set_states();
         let _ = self.text.try_insert_char(char_idx, '\n');
         self.x_pos = 0;
         self.on_down();
     }
     pub fn insert_chars(&mut self, chars: &str) {
-        self.past_states.push(self.text.clone());
-        self.future_states = vec![];
+        self.set_states();
         let char_idx = self.get_cursor_idx();
-        if self.text.try_insert(char_idx, &chars).is_ok() {
-            mut_add_safe(&mut self.x_pos, chars.len() as u16);
+        if self.text.try_insert(char_idx, chars).is_ok() {
+            mut_add_safe(&mut self.x_pos, chars.len());
         }
     }
     pub fn append_return(&mut self) {
-        let char_idx = add_safe(self.get_cursor_idx() as u16, 1);
-        self.past_states.push(self.text.clone());
-        self.future_states = vec![];
-        if self.text.try_insert_char(char_idx as usize, '\n').is_ok() {
-            mut_add_safe(&mut self.y_pos, 1);
-            self.x_pos = 0;
-        } else if self
-            .text
-            .try_insert_char(sub_safe(char_idx, 1) as usize, '\n')
-            .is_ok()
+        let char_idx = add_safe(self.get_cursor_idx(), 1);
+        self.set_states();
+        if self.text.try_insert_char(char_idx, '\n').is_ok()
+            || self
+                .text
+                .try_insert_char(sub_safe(char_idx, 1), '\n')
+                .is_ok()
         {
             mut_add_safe(&mut self.y_pos, 1);
             self.x_pos = 0;
@@ -448,37 +1158,244 @@ impl Buffer {
     }
 
     pub fn append_chars(&mut self, chars: &str) {
-        self.past_states.push(self.text.clone());
-        self.future_states = vec![];
-        let char_idx = add_safe(self.get_cursor_idx() as u16, 1);
-        if self.text.try_insert(char_idx as usize, &chars).is_ok() {
-            mut_add_safe(&mut self.x_pos, chars.len() as u16);
-        } else if self
-            .text
-            .try_insert(sub_safe(char_idx, 1) as usize, &chars)
-            .is_ok()
+        self.set_states();
+        let char_idx = add_safe(self.get_cursor_idx(), 1);
+        if self.text.try_insert(char_idx, chars).is_ok()
+            || self.text.try_insert(sub_safe(char_idx, 1), chars).is_ok()
         {
-            mut_add_safe(&mut self.x_pos, chars.len() as u16);
+            mut_add_safe(&mut self.x_pos, chars.len());
+        }
+    }
+
+    pub fn record_disk_state(&mut self) {
+        self.disk_state = self
+            .file_path
+            .as_ref()
+            .and_then(|p| DiskState::read(std::path::Path::new(p), !self.large_file));
+    }
+
+    pub fn is_file(&self, file_name: &str) -> bool {
+        match &self.file_path {
+            Some(file_path) => {
+                file_path == file_name
+                    || matches!(
+                        (std::fs::canonicalize(file_path), std::fs::canonicalize(file_name)),
+                        (Ok(a), Ok(b)) if a == b
+                    )
+            }
+            None => false,
+        }
+    }
+
+    pub fn disk_change(&self) -> DiskChange {
+        match (&self.file_path, &self.disk_state) {
+            (Some(file_path), Some(disk_state)) => {
+                disk_state.compare(std::path::Path::new(file_path))
+            }
+            _ => DiskChange::Unchanged,
+        }
+    }
+
+    pub fn swap_path(&self) -> Option<PathBuf> {
+        if self.scratch {
+            return None;
+        }
+        self.file_path.as_ref().map(|p| swap::swap_path(p))
+    }
+
+    pub fn needs_swap_write(&self) -> bool {
+        self.swap_conflict.is_none()
+            && !self.large_file
+            && self.swap_path().is_some()
+            && self.changed_tick != self.swap_tick
+    }
+
+    pub fn write_swap(&mut self) -> Result<(), std::io::Error> {
+        if let (Some(swap_path), Some(file_path)) = (self.swap_path(), &self.file_path) {
+            swap::write(&swap_path, file_path, &self.text)?;
+            self.swap_tick = self.changed_tick;
+        }
+        Ok(())
+    }
+
+    pub fn remove_swap(&mut self) {
+        if self.swap_conflict.is_none() {
+            if let Some(swap_path) = self.swap_path() {
+                swap::remove(&swap_path);
+            }
+        }
+    }
+
+    pub fn recover(&mut self) -> Result<(), std::io::Error> {
+        let swap_path = self.swap_path().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "E305: No swap file found")
+        })?;
+        let text = swap::read_contents(&swap_path)?;
+        self.set_states();
+        self.text = text;
+        if self.y_pos >= self.text.len_lines() {
+            self.move_to_last_line();
         }
+        self.x_pos = self.x_pos.min(sub_safe(self.current_line_len(), 1));
+        Ok(())
+    }
+
+    pub fn delete_stale_swap(&mut self) {
+        if let Some(swap_path) = self.swap_path() {
+            swap::remove(&swap_path);
+        }
+        self.swap_conflict = None;
+    }
+
+    pub fn search_forward(&mut self, pattern: &str) -> bool {
+        if pattern.is_empty() {
+            return false;
+        }
+        let text = self.text.to_string();
+        let start_byte = self
+            .text
+            .char_to_byte(add_safe(self.get_cursor_idx(), 1))
+            .min(text.len());
+        let found = text[start_byte..]
+            .find(pattern)
+            .map(|i| i + start_byte)
+            .or_else(|| text.find(pattern));
+        if let Some(byte_idx) = found {
+            self.move_to_char(self.text.byte_to_char(byte_idx));
+            return true;
+        }
+        false
+    }
+
+    pub fn move_to_char(&mut self, char_idx: usize) {
+        let char_idx = char_idx.min(self.text.len_chars());
+        let line = self.text.char_to_line(char_idx);
+        self.move_to_line_number(line + 1);
+        self.y_pos = line;
+        self.x_pos = char_idx - self.text.line_to_char(line);
+    }
+
+    fn byte_range_to_chars(&self, range: std::ops::Range<usize>) -> (usize, usize) {
+        (
+            self.text.byte_to_char(range.start),
+            self.text.byte_to_char(range.end),
+        )
+    }
+
+    /// Char range of the syntax tree text object under the cursor.
+    pub fn text_object_range(&mut self, object: TextObject, inner: bool) -> Option<(usize, usize)> {
+        self.sync_syntax_tree();
+        let byte = self.text.char_to_byte(self.get_cursor_idx());
+        let range = self
+            .syntax_tree
+            .as_ref()?
+            .text_object(byte, object, inner)?;
+        Some(self.byte_range_to_chars(range))
+    }
+
+    pub fn move_to_function(&mut self, forward: bool) -> bool {
+        self.sync_syntax_tree();
+        let byte = self.text.char_to_byte(self.get_cursor_idx());
+        let start = self
+            .syntax_tree
+            .as_ref()
+            .and_then(|tree| tree.function_start(byte, forward));
+        if let Some(start) = start {
+            self.move_to_char(self.text.byte_to_char(start));
+            return true;
+        }
+        false
+    }
+
+    /// Selects `start..end`, leaving the cursor on the last selected char.
+    pub fn select_range(&mut self, start: usize, end: usize) {
+        self.start_select_pos = Some(start);
+        self.end_select_pos = None;
+        self.select_mode = SelectMode::Char;
+        self.move_to_char(sub_safe(end, 1).max(start));
+    }
+
+    /// Grows the selection (or the char under the cursor) to the enclosing syntax node.
+    pub fn expand_selection(&mut self) -> bool {
+        self.sync_syntax_tree();
+        let cursor = self.get_cursor_idx();
+        let (start, end) = match self.start_select_pos {
+            Some(start) if start > cursor => (cursor, start + 1),
+            Some(start) => (start, cursor + 1),
+            None => (cursor, cursor + 1),
+        };
+        let end = end.min(self.text.len_chars());
+        let range = self.text.char_to_byte(start)..self.text.char_to_byte(end);
+        let parent = self
+            .syntax_tree
+            .as_ref()
+            .and_then(|tree| tree.parent_range(range));
+        if let Some(parent) = parent {
+            let (start, end) = self.byte_range_to_chars(parent);
+            self.select_range(start, end);
+            return true;
+        }
+        false
+    }
+
+    pub fn new_scratch(title: &str, text: &str) -> Result<Self, std::io::Error> {
+        let mut buffer = Self::new(None)?;
+        buffer.title = title.to_owned();
+        buffer.text = Rope::from_str(text);
+        buffer.scratch = true;
+        Ok(buffer)
     }
 
     pub fn new(file_name: Option<String>) -> Result<Self, std::io::Error> {
+        Self::open(file_name, options::DEFAULT_LARGE_FILE_BYTES)
+    }
+
+    /// Reads files over `large_file_bytes` into the rope chunk by chunk when they are UTF-8, so
+    /// no second full-size copy of the text is built; other encodings are decoded whole. The
+    /// whole file is still loaded up front: large-file mode turns off full-file highlighting,
+    /// the syntax tree and content hashing, but does not page the file in lazily.
+    fn load_large(
+        path: &std::path::Path,
+    ) -> Result<(Rope, Encoding, bool, LineEnding), std::io::Error> {
+        if let Some((rope, bom, line_ending)) = encoding::read_utf8(std::fs::File::open(path)?)? {
+            return Ok((rope, Encoding::Utf8, bom, line_ending));
+        }
+        let (text, encoding, bom, line_ending) = encoding::decode(&std::fs::read(path)?);
+        Ok((Rope::from_str(&text), encoding, bom, line_ending))
+    }
+
+    pub fn open(file_name: Option<String>, large_file_bytes: u64) -> Result<Self, std::io::Error> {
         match file_name {
             Some(file_path) => {
-                let rope = if std::path::Path::new(&file_path.trim()).exists() {
-                    let file = std::fs::File::open(&file_path.trim())?;
-                    let buf_reader = std::io::BufReader::new(file);
-                    Rope::from_reader(buf_reader)?
+                let path = std::path::Path::new(file_path.trim());
+                let large_file = std::fs::metadata(path)
+                    .map(|m| m.len() > large_file_bytes)
+                    .unwrap_or(false);
+                let (rope, encoding, bom, line_ending) = if large_file {
+                    Self::load_large(path)?
+                } else if path.exists() {
+                    let (text, encoding, bom, line_ending) =
+                        encoding::decode(&std::fs::read(path)?);
+                    (Rope::from_str(&text), encoding, bom, line_ending)
                 } else {
-                    Rope::new()
+                    (
+                        Rope::new(),
+                        Encoding::default(),
+                        false,
+                        LineEnding::default(),
+                    )
                 };
 
-                Ok(Self {
+                let mut buffer = Self {
                     id: Uuid::new_v4(),
+                    number: 0,
                     title: file_path.clone(),
                     clipboard: Arc::new(Mutex::new(Clipboard::new().unwrap())),
                     start_select_pos: None,
                     end_select_pos: None,
+                    select_mode: SelectMode::Char,
+                    block_to_eol: false,
+                    last_selection: None,
                     past_states: vec![],
                     future_states: vec![],
                     x_pos: 0,
@@ -488,14 +1405,35 @@ impl Buffer {
                     command_text: None,
                     current_page: 0,
                     page_size: 10,
-                })
+                    saved_state: Some(0),
+                    scratch: false,
+                    changed_tick: 0,
+                    swap_tick: 0,
+                    swap_conflict: swap::read_info(&swap::swap_path(file_path.trim()))
+                        .filter(|info| info.pid != std::process::id()),
+                    disk_state: DiskState::read(path, !large_file),
+                    large_file,
+                    encoding,
+                    bom,
+                    line_ending,
+                    filetype: String::new(),
+                    local_options: BufferOptions::default(),
+                    syntax_tree: None,
+                    folds: Folds::default(),
+                };
+                buffer.detect_filetype();
+                Ok(buffer)
             }
             None => Ok(Self {
                 id: Uuid::new_v4(),
+                number: 0,
                 title: "Ri".to_string(),
                 clipboard: Arc::new(Mutex::new(Clipboard::new().unwrap())),
                 start_select_pos: None,
                 end_select_pos: None,
+                select_mode: SelectMode::Char,
+                block_to_eol: false,
+                last_selection: None,
                 past_states: vec![],
                 future_states: vec![],
                 x_pos: 0,
@@ -505,6 +1443,20 @@ impl Buffer {
                 command_text: None,
                 current_page: 0,
                 page_size: 10,
+                saved_state: Some(0),
+                scratch: false,
+                changed_tick: 0,
+                swap_tick: 0,
+                swap_conflict: None,
+                disk_state: None,
+                large_file: false,
+                encoding: Encoding::default(),
+                bom: false,
+                line_ending: LineEnding::default(),
+                filetype: String::new(),
+                local_options: BufferOptions::default(),
+                syntax_tree: None,
+                folds: Folds::default(),
             }),
         }
     }
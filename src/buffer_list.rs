@@ -0,0 +1,86 @@
+use crate::buffer::Buffer;
+use anyhow::{Error as AnyHowError, Result as AnyHowResult};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const NO_ALTERNATE_ERR: &str = "E23: No alternate file";
+pub const LIST_TITLE: &str = "[Buffer List]";
+
+/// Buffers ordered by their buffer number, as `:ls` and `:bn`/`:bp` see them. Scratch
+/// buffers such as the `:ls` and `q:` windows are left out.
+pub fn by_number(buffers: &HashMap<Uuid, Buffer>) -> Vec<&Buffer> {
+    let mut listed = buffers
+        .values()
+        .filter(|b| !b.scratch)
+        .collect::<Vec<&Buffer>>();
+    listed.sort_by_key(|b| b.number);
+    listed
+}
+
+/// Resolves the argument of `:b`, `:bd` and `:bw`: a buffer number, an exact name, or a
+/// part of a name that only one buffer matches.
+pub fn find(buffers: &HashMap<Uuid, Buffer>, arg: &str) -> AnyHowResult<Uuid> {
+    let arg = arg.trim();
+    if let Ok(number) = arg.parse::<usize>() {
+        return buffers
+            .values()
+            .find(|b| b.number == number)
+            .map(|b| b.id)
+            .ok_or_else(|| AnyHowError::msg(format!("E86: Buffer {} does not exist", number)));
+    }
+    if let Some(buffer) = buffers.values().find(|b| b.title == arg) {
+        return Ok(buffer.id);
+    }
+    let matches = by_number(buffers)
+        .into_iter()
+        .filter(|b| b.title.contains(arg))
+        .collect::<Vec<&Buffer>>();
+    match &matches[..] {
+        [buffer] => Ok(buffer.id),
+        [] => Err(AnyHowError::msg(format!(
+            "E94: No matching buffer for {}",
+            arg
+        ))),
+        _ => Err(AnyHowError::msg(format!(
+            "E93: More than one match for {}",
+            arg
+        ))),
+    }
+}
+
+/// The buffer after (or before) `current` in number order, wrapping around. From a scratch
+/// buffer it is the first (or last) listed one.
+pub fn cycle(buffers: &HashMap<Uuid, Buffer>, current: Uuid, forward: bool) -> Option<Uuid> {
+    let listed = by_number(buffers);
+    let idx = match listed.iter().position(|b| b.id == current) {
+        Some(idx) => idx,
+        None if forward => return listed.first().map(|b| b.id),
+        None => return listed.last().map(|b| b.id),
+    };
+    let next = if forward {
+        (idx + 1) % listed.len()
+    } else {
+        (idx + listed.len() - 1) % listed.len()
+    };
+    listed.get(next).map(|b| b.id)
+}
+
+/// One `:ls` entry, e.g. `  3 #h + "src/app.rs" line 12`. `%` marks the current buffer,
+/// `#` the alternate, `a` a buffer shown in a window and `h` a hidden one.
+pub fn listing(buffer: &Buffer, current: bool, alternate: bool, displayed: bool) -> String {
+    format!(
+        "{:>3} {}{} {} \"{}\" line {}",
+        buffer.number,
+        if current {
+            '%'
+        } else if alternate {
+            '#'
+        } else {
+            ' '
+        },
+        if displayed { 'a' } else { 'h' },
+        if buffer.is_modified() { '+' } else { ' ' },
+        buffer.title,
+        buffer.y_pos + 1
+    )
+}
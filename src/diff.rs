@@ -0,0 +1,65 @@
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Line diff between `old` and `new` in unified style (` `, `-`, `+` prefixes). Falls back to
+/// listing the whole changed region as removed then added when it is too large for the LCS table.
+pub fn diff_lines(old: &[&str], new: &[&str]) -> Vec<String> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut lines = old[..prefix]
+        .iter()
+        .map(|l| format!("  {}", l))
+        .collect::<Vec<String>>();
+    if old_mid.len().saturating_mul(new_mid.len()) > MAX_DIFF_CELLS {
+        lines.extend(old_mid.iter().map(|l| format!("- {}", l)));
+        lines.extend(new_mid.iter().map(|l| format!("+ {}", l)));
+    } else {
+        lines.extend(lcs_diff(old_mid, new_mid));
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|l| format!("  {}", l)));
+    lines
+}
+
+fn lcs_diff(old: &[&str], new: &[&str]) -> Vec<String> {
+    let width = new.len() + 1;
+    let mut table = vec![0_usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            table[i * width + j] = if old[i] == new[j] {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                table[(i + 1) * width + j].max(table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|l| format!("- {}", l)));
+    lines.extend(new[j..].iter().map(|l| format!("+ {}", l)));
+    lines
+}
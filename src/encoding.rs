@@ -0,0 +1,268 @@
+use anyhow::{Error as AnyHowError, Result as AnyHowResult};
+use ropey::{Rope, RopeBuilder};
+use std::io::{self, Read};
+
+const CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+
+    pub fn from_name(name: &str) -> AnyHowResult<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" | "ucs-2le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" | "utf-16" | "utf16" => Ok(Encoding::Utf16Be),
+            "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(AnyHowError::msg(format!(
+                "E474: Invalid argument: fileencoding={}",
+                name
+            ))),
+        }
+    }
+
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            Encoding::Latin1 => &[],
+        }
+    }
+}
+
+impl LineEnding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::Crlf => "dos",
+            LineEnding::Cr => "mac",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    pub fn from_name(name: &str) -> AnyHowResult<Self> {
+        match name {
+            "unix" => Ok(LineEnding::Lf),
+            "dos" => Ok(LineEnding::Crlf),
+            "mac" => Ok(LineEnding::Cr),
+            _ => Err(AnyHowError::msg(format!(
+                "E474: Invalid argument: fileformat={}",
+                name
+            ))),
+        }
+    }
+}
+
+/// Returns the detected encoding and whether the bytes start with its byte order mark. A
+/// UTF-8 mark followed by bytes that are not UTF-8 is read as Latin-1, mark included, so
+/// writing the file back leaves every byte as it was.
+pub fn detect_encoding(bytes: &[u8]) -> (Encoding, bool) {
+    let with_bom = [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be]
+        .iter()
+        .copied()
+        .find(|encoding| bytes.starts_with(encoding.bom()));
+    let broken_utf8 = with_bom == Some(Encoding::Utf8)
+        && std::str::from_utf8(&bytes[Encoding::Utf8.bom().len()..]).is_err();
+    if broken_utf8 {
+        (Encoding::Latin1, false)
+    } else if let Some(encoding) = with_bom {
+        (encoding, true)
+    } else if let Some(encoding) = detect_utf16_without_bom(bytes) {
+        (encoding, false)
+    } else if std::str::from_utf8(bytes).is_ok() {
+        (Encoding::Utf8, false)
+    } else {
+        (Encoding::Latin1, false)
+    }
+}
+
+/// ASCII-heavy UTF-16 has a zero in every other byte; which half is zero gives the byte order.
+fn detect_utf16_without_bom(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 4 || bytes.len() % 2 != 0 {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if odd_zeros * 10 >= pairs * 9 && even_zeros == 0 {
+        Some(Encoding::Utf16Le)
+    } else if even_zeros * 10 >= pairs * 9 && odd_zeros == 0 {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+pub fn detect_line_ending(text: &str) -> LineEnding {
+    let crlf = text.matches("\r\n").count();
+    let cr = text.matches('\r').count() - crlf;
+    let lf = text.matches('\n').count() - crlf;
+    if crlf > 0 && lf == 0 && cr == 0 {
+        LineEnding::Crlf
+    } else if cr > 0 && lf == 0 && crlf == 0 {
+        LineEnding::Cr
+    } else {
+        LineEnding::Lf
+    }
+}
+
+/// Decodes file contents into text with `\n` line endings, returning what was detected so
+/// the file can be written back the same way.
+pub fn decode(bytes: &[u8]) -> (String, Encoding, bool, LineEnding) {
+    let (encoding, bom) = detect_encoding(bytes);
+    let body = if bom {
+        &bytes[encoding.bom().len()..]
+    } else {
+        bytes
+    };
+    let text = match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(body).into_owned(),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let units = body
+                .chunks(2)
+                .map(|c| match (encoding, c) {
+                    (Encoding::Utf16Le, [lo, hi]) => u16::from_le_bytes([*lo, *hi]),
+                    (_, [hi, lo]) => u16::from_be_bytes([*hi, *lo]),
+                    (_, [single]) => *single as u16,
+                    _ => 0,
+                })
+                .collect::<Vec<u16>>();
+            String::from_utf16_lossy(&units)
+        }
+        Encoding::Latin1 => body.iter().map(|b| *b as char).collect::<String>(),
+    };
+    let line_ending = detect_line_ending(&text);
+    let text = match line_ending {
+        LineEnding::Lf => text,
+        LineEnding::Crlf => text.replace("\r\n", "\n"),
+        LineEnding::Cr => text.replace('\r', "\n"),
+    };
+    (text, encoding, bom, line_ending)
+}
+
+/// Reads UTF-8 text into a rope one chunk at a time, converting line endings as it goes so
+/// the whole file is never held as a second string. The byte order mark and line ending are
+/// taken from the first chunk. Returns `None` when the text is not UTF-8, leaving the caller
+/// to fall back to `decode`.
+pub fn read_utf8(mut reader: impl Read) -> io::Result<Option<(Rope, bool, LineEnding)>> {
+    let mut builder = RopeBuilder::new();
+    let mut chunk = vec![0_u8; CHUNK_BYTES];
+    let mut pending = vec![];
+    let mut format = None;
+    let mut carried_cr = false;
+    loop {
+        let mut filled = 0;
+        while filled < chunk.len() {
+            match reader.read(&mut chunk[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        let eof = filled < chunk.len();
+        if format.is_none() && detect_utf16_without_bom(&chunk[..filled]).is_some() {
+            return Ok(None);
+        }
+        pending.extend_from_slice(&chunk[..filled]);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() && !eof => e.valid_up_to(),
+            Err(_) => return Ok(None),
+        };
+        let rest = pending.split_off(valid);
+        let bytes = std::mem::replace(&mut pending, rest);
+        let mut text =
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if carried_cr {
+            text.insert(0, '\r');
+        }
+        carried_cr = !eof && text.ends_with('\r');
+        if carried_cr {
+            text.pop();
+        }
+        let (bom, line_ending) = match format {
+            Some(format) => format,
+            None => {
+                let bom = text.starts_with('\u{feff}');
+                if bom {
+                    text.remove(0);
+                }
+                *format.insert((bom, detect_line_ending(&text)))
+            }
+        };
+        match line_ending {
+            LineEnding::Lf => builder.append(&text),
+            LineEnding::Crlf => builder.append(&text.replace("\r\n", "\n")),
+            LineEnding::Cr => builder.append(&text.replace('\r', "\n")),
+        }
+        if eof {
+            return Ok(Some((builder.finish(), bom, line_ending)));
+        }
+    }
+}
+
+pub fn encode(
+    text: &str,
+    encoding: Encoding,
+    bom: bool,
+    line_ending: LineEnding,
+) -> io::Result<Vec<u8>> {
+    let converted;
+    let text = if line_ending == LineEnding::Lf {
+        text
+    } else {
+        converted = text.replace('\n', line_ending.as_str());
+        &converted
+    };
+    let mut bytes = if bom { encoding.bom().to_vec() } else { vec![] };
+    match encoding {
+        Encoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+        Encoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes())),
+        Encoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(|u| u.to_be_bytes())),
+        Encoding::Latin1 => bytes.extend(
+            text.chars()
+                .map(|c| {
+                    if (c as u32) <= 0xFF {
+                        Ok(c as u8)
+                    } else {
+                        Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "E513: write error, conversion failed",
+                        ))
+                    }
+                })
+                .collect::<io::Result<Vec<u8>>>()?,
+        ),
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,175 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiskState {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    pub hash: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiskChange {
+    Unchanged,
+    Changed,
+    Deleted,
+}
+
+impl DiskState {
+    pub fn read(path: &Path, with_hash: bool) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: if with_hash {
+                Some(hash_file(path).ok()?)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Compares against the file on disk, only hashing its contents when mtime or size moved
+    /// so a `touch` or a no-op rewrite isn't reported as a change.
+    pub fn compare(&self, path: &Path) -> DiskChange {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return DiskChange::Deleted,
+        };
+        if metadata.modified().ok() == self.modified && metadata.len() == self.len {
+            return DiskChange::Unchanged;
+        }
+        match self.hash {
+            Some(recorded) => match hash_file(path) {
+                Ok(hash) if hash == recorded && metadata.len() == self.len => DiskChange::Unchanged,
+                Ok(_) => DiskChange::Changed,
+                Err(_) => DiskChange::Deleted,
+            },
+            None => DiskChange::Changed,
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let contents = fs::read(path)?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push("~");
+    path.with_file_name(name)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Writes through a temp file in the same directory and renames it over `path`, so a crash
+/// or a full disk mid-write leaves the original untouched. Files we can't replace without
+/// changing their owner or breaking hard links are rewritten in place instead.
+pub fn write_atomic<F>(path: &Path, backup: bool, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = fs::metadata(&path).ok();
+    if backup && metadata.is_some() {
+        fs::copy(&path, backup_path(&path))?;
+    }
+
+    let temp_path = temp_path(&path);
+    let temp_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)?;
+    if let (Some(original), Ok(temp)) = (&metadata, temp_file.metadata()) {
+        if !can_replace(original, &temp) {
+            drop(temp_file);
+            let _ = fs::remove_file(&temp_path);
+            return write_in_place(&path, write);
+        }
+    }
+
+    let result = write_file(temp_file, &metadata, write).and_then(|_| {
+        fs::rename(&temp_path, &path)?;
+        sync_parent(&path);
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_file<F>(file: File, metadata: &Option<Metadata>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    if let Some(metadata) = metadata {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()
+}
+
+fn write_in_place<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let file = OpenOptions::new().write(true).truncate(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+pub fn append_to<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+#[cfg(unix)]
+fn can_replace(original: &Metadata, temp: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    original.uid() == temp.uid() && original.gid() == temp.gid() && original.nlink() <= 1
+}
+
+#[cfg(not(unix))]
+fn can_replace(_original: &Metadata, _temp: &Metadata) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) {
+    if let Some(parent) = path.parent() {
+        let dir = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) {}
@@ -0,0 +1,177 @@
+use crate::options::BufferOptions;
+use std::path::Path;
+
+/// Known filetypes with the extensions that select them. The first extension doubles as the
+/// token used to look the syntax up in syntect.
+const EXTENSIONS: &[(&str, &[&str])] = &[
+    ("rust", &["rs"]),
+    ("python", &["py", "pyw", "pyi"]),
+    ("markdown", &["md", "markdown", "mdown"]),
+    ("toml", &["toml"]),
+    ("json", &["json"]),
+    ("yaml", &["yaml", "yml"]),
+    ("javascript", &["js", "mjs", "cjs"]),
+    ("c", &["c", "h"]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh"]),
+    ("go", &["go"]),
+    ("java", &["java"]),
+    ("ruby", &["rb"]),
+    ("lua", &["lua"]),
+    ("perl", &["pl", "pm"]),
+    ("sh", &["sh", "bash", "zsh"]),
+    ("html", &["html", "htm"]),
+    ("css", &["css"]),
+    ("xml", &["xml"]),
+    ("sql", &["sql"]),
+    ("make", &["mk", "mak"]),
+    ("text", &["txt"]),
+];
+
+const FILENAMES: &[(&str, &str)] = &[
+    ("Cargo.lock", "toml"),
+    ("Makefile", "make"),
+    ("makefile", "make"),
+    ("GNUmakefile", "make"),
+    (".bashrc", "sh"),
+    (".bash_profile", "sh"),
+    (".zshrc", "sh"),
+    (".profile", "sh"),
+];
+
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("python", "python"),
+    ("sh", "sh"),
+    ("bash", "sh"),
+    ("zsh", "sh"),
+    ("dash", "sh"),
+    ("node", "javascript"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("lua", "lua"),
+    ("make", "make"),
+];
+
+pub const MODELINE_SCAN: usize = 5;
+
+/// Detects the filetype of a file from, in order of precedence, a modeline, its file name,
+/// its extension and a shebang on the first line. Returns an empty string when nothing matches.
+pub fn detect(file_path: Option<&str>, lines: &[String]) -> String {
+    let modeline_filetype =
+        modeline_options(lines)
+            .into_iter()
+            .rev()
+            .find_map(|option| match option.split_once('=') {
+                Some(("filetype" | "ft", value)) => Some(value.to_owned()),
+                _ => None,
+            });
+    modeline_filetype
+        .or_else(|| file_path.and_then(from_path))
+        .or_else(|| lines.first().and_then(|l| from_shebang(l)))
+        .unwrap_or_default()
+}
+
+pub fn from_path(file_path: &str) -> Option<String> {
+    let path = Path::new(file_path.trim());
+    let name = path.file_name()?.to_string_lossy();
+    if let Some((_, filetype)) = FILENAMES.iter().find(|(n, _)| *n == name) {
+        return Some(filetype.to_string());
+    }
+    let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&extension.as_str()))
+        .map(|(filetype, _)| filetype.to_string())
+}
+
+pub fn from_shebang(line: &str) -> Option<String> {
+    let command = line.strip_prefix("#!")?.trim();
+    let mut words = command.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .map(|(_, filetype)| filetype.to_string())
+}
+
+/// Option assignments from `vim:`/`vi:`/`ri:` modelines in the first or last few lines,
+/// in both the `vim: set ts=4 ft=rust:` and the `vim: ts=4 ft=rust` forms.
+pub fn modeline_options(lines: &[String]) -> Vec<String> {
+    let tail_start = lines.len().saturating_sub(MODELINE_SCAN).max(MODELINE_SCAN);
+    lines
+        .iter()
+        .take(MODELINE_SCAN)
+        .chain(lines.iter().skip(tail_start))
+        .filter_map(|line| parse_modeline(line))
+        .flatten()
+        .collect()
+}
+
+fn parse_modeline(line: &str) -> Option<Vec<String>> {
+    let rest = ["vim:", "vi:", "ri:", "ex:"].iter().find_map(|marker| {
+        line.find(marker)
+            .filter(|idx| *idx == 0 || line[..*idx].ends_with(char::is_whitespace))
+            .map(|idx| &line[idx + marker.len()..])
+    })?;
+    let rest = rest.trim_start();
+    let options = match rest
+        .strip_prefix("set ")
+        .or_else(|| rest.strip_prefix("se "))
+    {
+        Some(set) => set.split(':').next().unwrap_or_default(),
+        None => rest,
+    };
+    let options = options
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .filter(|o| !o.is_empty())
+        .map(|o| o.to_owned())
+        .collect::<Vec<String>>();
+    if options.is_empty() {
+        None
+    } else {
+        Some(options)
+    }
+}
+
+/// Token to look a filetype's syntax up by in syntect, which matches it against both the
+/// extensions and the names of its syntaxes.
+pub fn syntax_token(filetype: &str) -> &str {
+    EXTENSIONS
+        .iter()
+        .find(|(name, _)| *name == filetype)
+        .and_then(|(_, extensions)| extensions.first().copied())
+        .unwrap_or(filetype)
+}
+
+pub fn default_options(filetype: &str) -> BufferOptions {
+    match filetype {
+        "rust" => BufferOptions {
+            tabstop: 4,
+            shiftwidth: 4,
+            expandtab: true,
+            treesitter: true,
+            ..BufferOptions::default()
+        },
+        "python" | "java" | "c" | "cpp" | "toml" | "markdown" | "sql" => BufferOptions {
+            tabstop: 4,
+            shiftwidth: 4,
+            expandtab: true,
+            ..BufferOptions::default()
+        },
+        "javascript" | "json" | "yaml" | "html" | "css" | "xml" | "ruby" | "lua" => BufferOptions {
+            tabstop: 2,
+            shiftwidth: 2,
+            expandtab: true,
+            ..BufferOptions::default()
+        },
+        "go" => BufferOptions {
+            tabstop: 4,
+            shiftwidth: 4,
+            ..BufferOptions::default()
+        },
+        _ => BufferOptions::default(),
+    }
+}
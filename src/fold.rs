@@ -0,0 +1,389 @@
+use crate::syntax_tree::SyntaxTree;
+use anyhow::{Error as AnyHowError, Result as AnyHowResult};
+use ropey::Rope;
+
+pub const FOLD_OPEN_MARKER: &str = "{{{";
+pub const FOLD_CLOSE_MARKER: &str = "}}}";
+pub const NO_FOLD_ERR: &str = "E490: No fold found";
+pub const FOLD_METHOD_ERR: &str = "E350: Cannot create fold with current 'foldmethod'";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FoldMethod {
+    #[default]
+    Manual,
+    Indent,
+    Marker,
+    Syntax,
+}
+
+impl FoldMethod {
+    pub fn parse(value: &str) -> AnyHowResult<Self> {
+        match value {
+            "manual" => Ok(FoldMethod::Manual),
+            "indent" => Ok(FoldMethod::Indent),
+            "marker" => Ok(FoldMethod::Marker),
+            "syntax" => Ok(FoldMethod::Syntax),
+            _ => Err(AnyHowError::msg(format!(
+                "E474: Invalid argument: foldmethod={}",
+                value
+            ))),
+        }
+    }
+}
+
+/// A fold over the lines `start..=end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fold {
+    pub start: usize,
+    pub end: usize,
+    pub closed: bool,
+}
+
+impl Fold {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            closed: false,
+        }
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        self.start <= line && line <= self.end
+    }
+}
+
+/// The folds of a buffer, kept sorted by start line with enclosing folds before the ones
+/// they contain. Folds from the indent, marker and syntax methods are recomputed when the
+/// text changes; a recomputed fold stays closed if a closed fold started on the same line.
+#[derive(Clone, Debug, Default)]
+pub struct Folds {
+    pub method: FoldMethod,
+    pub folds: Vec<Fold>,
+    tick: Option<usize>,
+}
+
+impl Folds {
+    pub fn update(
+        &mut self,
+        method: FoldMethod,
+        text: &Rope,
+        shiftwidth: usize,
+        syntax_tree: Option<&SyntaxTree>,
+        changed_tick: usize,
+    ) {
+        if self.method == method && self.tick == Some(changed_tick) {
+            return;
+        }
+        if self.method != method {
+            self.folds.clear();
+        }
+        self.method = method;
+        self.tick = Some(changed_tick);
+        let line_count = text.len_lines();
+        let folds = match method {
+            FoldMethod::Manual => {
+                self.folds.retain(|f| f.start < line_count);
+                self.folds
+                    .iter_mut()
+                    .for_each(|f| f.end = f.end.min(line_count.saturating_sub(1)));
+                return;
+            }
+            FoldMethod::Indent => indent_folds(text, shiftwidth),
+            FoldMethod::Marker => marker_folds(text),
+            FoldMethod::Syntax => syntax_tree.map(syntax_folds).unwrap_or_default(),
+        };
+        let closed = self
+            .folds
+            .iter()
+            .filter(|f| f.closed)
+            .map(|f| f.start)
+            .collect::<Vec<usize>>();
+        self.folds = folds
+            .into_iter()
+            .map(|f| Fold {
+                closed: closed.contains(&f.start),
+                ..f
+            })
+            .collect();
+        sort(&mut self.folds);
+    }
+
+    /// Creates a closed fold over `start..=end`, which only the manual method allows.
+    pub fn create(&mut self, start: usize, end: usize) -> AnyHowResult<()> {
+        if self.method != FoldMethod::Manual {
+            return Err(AnyHowError::msg(FOLD_METHOD_ERR));
+        }
+        let (start, end) = (start.min(end), start.max(end));
+        self.folds.retain(|f| !(f.start == start && f.end == end));
+        self.folds.push(Fold {
+            closed: true,
+            ..Fold::new(start, end)
+        });
+        sort(&mut self.folds);
+        Ok(())
+    }
+
+    /// Moves manual folds for a line inserted at `line`: folds below it shift down and a fold
+    /// it lands inside grows. Other methods recompute their folds from the text instead.
+    pub fn insert_line(&mut self, line: usize) {
+        if self.method != FoldMethod::Manual {
+            return;
+        }
+        for fold in self.folds.iter_mut() {
+            if line <= fold.start {
+                fold.start += 1;
+                fold.end += 1;
+            } else if line <= fold.end {
+                fold.end += 1;
+            }
+        }
+    }
+
+    /// Moves manual folds for the removed `line`, dropping a fold that only covered that line.
+    pub fn remove_line(&mut self, line: usize) {
+        if self.method != FoldMethod::Manual {
+            return;
+        }
+        self.folds.retain(|f| !(f.start == line && f.end == line));
+        for fold in self.folds.iter_mut() {
+            if line < fold.start {
+                fold.start -= 1;
+                fold.end -= 1;
+            } else if line <= fold.end {
+                fold.end -= 1;
+            }
+        }
+        sort(&mut self.folds);
+    }
+
+    /// Lines hidden behind each closed fold that is not itself inside a closed fold.
+    pub fn closed_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        for fold in self.folds.iter().filter(|f| f.closed) {
+            match ranges.last() {
+                Some((_, end)) if fold.start <= *end => (),
+                _ => ranges.push((fold.start, fold.end)),
+            }
+        }
+        ranges
+    }
+
+    pub fn closed_fold_at(&self, line: usize) -> Option<(usize, usize)> {
+        self.closed_ranges()
+            .into_iter()
+            .find(|(start, end)| *start <= line && line <= *end)
+    }
+
+    pub fn level(&self, line: usize) -> usize {
+        self.folds.iter().filter(|f| f.contains(line)).count()
+    }
+
+    /// Opens the closed fold at `line`, or the innermost fold containing it.
+    pub fn open(&mut self, line: usize) -> AnyHowResult<()> {
+        let start = self.closed_fold_at(line).map(|(start, _)| start);
+        let fold = match start {
+            Some(start) => self.folds.iter_mut().find(|f| f.closed && f.start == start),
+            None => self.folds.iter_mut().rev().find(|f| f.contains(line)),
+        };
+        fold.map(|f| f.closed = false)
+            .ok_or_else(|| AnyHowError::msg(NO_FOLD_ERR))
+    }
+
+    /// Closes the innermost open fold containing `line`, so repeating it closes the folds
+    /// around that one in turn.
+    pub fn close(&mut self, line: usize) -> AnyHowResult<()> {
+        let idx = self
+            .folds
+            .iter()
+            .rposition(|f| f.contains(line) && !f.closed)
+            .ok_or_else(|| AnyHowError::msg(NO_FOLD_ERR))?;
+        self.folds[idx].closed = true;
+        Ok(())
+    }
+
+    pub fn toggle(&mut self, line: usize) -> AnyHowResult<()> {
+        if self.closed_fold_at(line).is_some() {
+            self.open(line)
+        } else {
+            self.close(line)
+        }
+    }
+
+    pub fn set_all(&mut self, closed: bool) {
+        self.folds.iter_mut().for_each(|f| f.closed = closed);
+    }
+
+    /// Start of the next fold below `line`, for `zj`.
+    pub fn next_start(&self, line: usize) -> Option<usize> {
+        self.folds
+            .iter()
+            .map(|f| f.start)
+            .filter(|start| *start > line)
+            .min()
+    }
+
+    /// End of the previous fold above `line`, for `zk`.
+    pub fn prev_end(&self, line: usize) -> Option<usize> {
+        self.folds
+            .iter()
+            .map(|f| f.end)
+            .filter(|end| *end < line)
+            .max()
+    }
+
+    /// The line `j` moves to from `line`, stepping over a closed fold as a single line.
+    pub fn line_below(&self, line: usize) -> usize {
+        let line = self
+            .closed_fold_at(line)
+            .map(|(_, end)| end)
+            .unwrap_or(line);
+        line + 1
+    }
+
+    /// The line `k` moves to from `line`, landing on the start of a closed fold above.
+    pub fn line_above(&self, line: usize) -> usize {
+        let line = self
+            .closed_fold_at(line)
+            .map(|(start, _)| start)
+            .unwrap_or(line)
+            .saturating_sub(1);
+        self.closed_fold_at(line)
+            .map(|(start, _)| start)
+            .unwrap_or(line)
+    }
+
+    /// Marker shown in the fold column: `+` on a closed fold, `-` where a fold starts and `|`
+    /// inside an open one.
+    pub fn column_marker(&self, line: usize) -> char {
+        if self.closed_fold_at(line).is_some() {
+            '+'
+        } else if self.folds.iter().any(|f| f.start == line) {
+            '-'
+        } else if self.level(line) > 0 {
+            '|'
+        } else {
+            ' '
+        }
+    }
+}
+
+fn sort(folds: &mut Vec<Fold>) {
+    folds.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    folds.dedup_by(|a, b| a.start == b.start && a.end == b.end);
+}
+
+/// Folds from nesting stacks: `levels` gives each line's fold depth.
+fn folds_from_levels(levels: &[usize]) -> Vec<Fold> {
+    let mut folds = vec![];
+    let mut open: Vec<usize> = vec![];
+    for (line, level) in levels.iter().enumerate() {
+        while open.len() > *level {
+            if let Some(start) = open.pop() {
+                folds.push(Fold::new(start, line - 1));
+            }
+        }
+        while open.len() < *level {
+            open.push(line);
+        }
+    }
+    while let Some(start) = open.pop() {
+        folds.push(Fold::new(start, levels.len().saturating_sub(1)));
+    }
+    folds
+}
+
+/// One fold level per `shiftwidth` of indent. Blank lines take the lower level of the
+/// lines around them so they don't split a block.
+pub fn indent_folds(text: &Rope, shiftwidth: usize) -> Vec<Fold> {
+    let shiftwidth = shiftwidth.max(1);
+    let indents = text
+        .lines()
+        .map(|line| {
+            let line = line.to_string();
+            if line.trim().is_empty() {
+                return None;
+            }
+            let width = line
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .map(|c| if c == '\t' { shiftwidth } else { 1 })
+                .sum::<usize>();
+            Some(width / shiftwidth)
+        })
+        .collect::<Vec<Option<usize>>>();
+    let levels = indents
+        .iter()
+        .enumerate()
+        .map(|(idx, indent)| {
+            indent.unwrap_or_else(|| {
+                let before = indents[..idx].iter().rev().find_map(|i| *i);
+                let after = indents[idx + 1..].iter().find_map(|i| *i);
+                before.unwrap_or(0).min(after.unwrap_or(0))
+            })
+        })
+        .collect::<Vec<usize>>();
+    folds_from_levels(&levels)
+}
+
+/// Folds between `{{{` and `}}}` markers. Markers left open run to the end of the text.
+pub fn marker_folds(text: &Rope) -> Vec<Fold> {
+    let mut folds = vec![];
+    let mut open: Vec<usize> = vec![];
+    let last_line = text.len_lines().saturating_sub(1);
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.to_string();
+        for _ in line.matches(FOLD_OPEN_MARKER) {
+            open.push(idx);
+        }
+        for _ in line.matches(FOLD_CLOSE_MARKER) {
+            if let Some(start) = open.pop() {
+                folds.push(Fold::new(start, idx));
+            }
+        }
+    }
+    while let Some(start) = open.pop() {
+        folds.push(Fold::new(start, last_line));
+    }
+    folds
+}
+
+/// Folds over every function and class-like node spanning more than one line.
+pub fn syntax_folds(syntax_tree: &SyntaxTree) -> Vec<Fold> {
+    let tree = match syntax_tree.tree() {
+        Some(tree) => tree,
+        None => return vec![],
+    };
+    let kinds = syntax_tree.kinds;
+    let mut folds = vec![];
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        let (start, end) = (node.start_position().row, node.end_position().row);
+        if end > start
+            && (kinds.functions.contains(&node.kind()) || kinds.classes.contains(&node.kind()))
+        {
+            folds.push(Fold::new(start, end));
+        }
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return folds;
+            }
+        }
+    }
+}
+
+/// The line drawn in place of a closed fold, e.g. `+--  12 lines: impl Buffer {`.
+pub fn summary(text: &Rope, start: usize, end: usize, level: usize) -> String {
+    let first = text.line(start).to_string();
+    let first = first.trim().replace(FOLD_OPEN_MARKER, "").trim().to_owned();
+    format!(
+        "+-{}{:>3} lines: {}",
+        "-".repeat(level.saturating_sub(1)),
+        end - start + 1,
+        first
+    )
+}
@@ -0,0 +1,303 @@
+use crate::filetype;
+use crate::syntax_tree::SyntaxTree;
+use crate::theme::{self, ColorSupport, UiTheme};
+use crate::window::CachedSpan;
+use anyhow::{Error as AnyHowError, Result as AnyHowResult};
+use ratatui::style::Style;
+use ropey::Rope;
+use std::sync::Arc;
+use syntect::highlighting::{
+    HighlightIterator, HighlightState, Highlighter, Style as SyntectStyle, Theme, ThemeSet,
+};
+use syntect::parsing::{
+    ParseState, Scope, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet,
+};
+
+type LineState = (ParseState, HighlightState);
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Syntaxes for filetypes that syntect's defaults lack, compiled into the binary.
+const BUNDLED_SYNTAXES: &[&str] = &[include_str!("../assets/syntaxes/TOML.sublime-syntax")];
+
+/// Syntax definitions and themes, loaded once and shared by every window.
+#[derive(Clone)]
+pub struct Highlighting {
+    pub syntax_set: Arc<SyntaxSet>,
+    pub theme_set: Arc<ThemeSet>,
+    pub theme_name: String,
+    pub theme: Arc<Theme>,
+    pub ui_theme: UiTheme,
+    pub color_support: ColorSupport,
+}
+
+impl Highlighting {
+    /// Loads the built-in syntaxes and themes plus any `.tmTheme` files in the user's theme
+    /// directory; a user theme with a built-in's name replaces it.
+    pub fn load() -> Self {
+        let mut theme_set = ThemeSet::load_defaults();
+        if let Some(dir) = theme::theme_dir().filter(|dir| dir.is_dir()) {
+            let _ = theme_set.add_from_folder(dir);
+        }
+        let color_support = ColorSupport::detect();
+        let theme = theme_set.themes[DEFAULT_THEME].clone();
+        Self {
+            syntax_set: Arc::new(Self::load_syntax_set()),
+            ui_theme: UiTheme::from_theme(&theme, color_support),
+            theme_set: Arc::new(theme_set),
+            theme_name: DEFAULT_THEME.to_owned(),
+            theme: Arc::new(theme),
+            color_support,
+        }
+    }
+
+    fn load_syntax_set() -> SyntaxSet {
+        let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+        for source in BUNDLED_SYNTAXES {
+            if let Ok(syntax) = SyntaxDefinition::load_from_str(source, true, None) {
+                builder.add(syntax);
+            }
+        }
+        builder.build()
+    }
+
+    pub fn theme_names(&self) -> Vec<String> {
+        let mut names = self
+            .theme_set
+            .themes
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    pub fn set_theme(&mut self, name: &str) -> AnyHowResult<()> {
+        let theme = self.theme_set.themes.get(name).ok_or_else(|| {
+            AnyHowError::msg(format!("E185: Cannot find color scheme '{}'", name))
+        })?;
+        self.ui_theme = UiTheme::from_theme(theme, self.color_support);
+        self.theme = Arc::new(theme.clone());
+        self.theme_name = name.to_owned();
+        Ok(())
+    }
+
+    pub fn syntax_for(&self, filetype: &str) -> &SyntaxReference {
+        self.syntax_set
+            .find_syntax_by_token(filetype::syntax_token(filetype))
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+    }
+
+    pub fn cache_for(&self, filetype: &str, tree_sitter: bool) -> HighlightCache {
+        let mut cache = HighlightCache::new(
+            self.syntax_set.clone(),
+            self.syntax_for(filetype).clone(),
+            self.theme.clone(),
+        );
+        cache.color_support = self.color_support;
+        cache.tree_sitter = tree_sitter;
+        cache
+    }
+}
+
+/// Syntect highlighting with a parse/highlight state checkpoint at the start of every line.
+/// Edits only mark lines stale; `highlight` re-parses from the first stale line and stops
+/// trusting old checkpoints once the new state matches them again. With `tree_sitter` set the
+/// spans come from the buffer's parse tree instead, see `highlight_tree`.
+#[derive(Clone)]
+pub struct HighlightCache {
+    pub syntax_set: Arc<SyntaxSet>,
+    pub syntax: SyntaxReference,
+    pub theme: Arc<Theme>,
+    pub color_support: ColorSupport,
+    pub tree_sitter: bool,
+    capture_styles: Vec<Style>,
+    states: Vec<LineState>,
+    dirty_from: usize,
+    converge_after: usize,
+}
+
+impl HighlightCache {
+    pub fn new(syntax_set: Arc<SyntaxSet>, syntax: SyntaxReference, theme: Arc<Theme>) -> Self {
+        let initial = Self::initial_state(&syntax, &theme);
+        Self {
+            syntax_set,
+            syntax,
+            theme,
+            color_support: ColorSupport::default(),
+            tree_sitter: false,
+            capture_styles: vec![],
+            states: vec![initial],
+            dirty_from: 0,
+            converge_after: 0,
+        }
+    }
+
+    fn initial_state(syntax: &SyntaxReference, theme: &Theme) -> LineState {
+        let highlighter = Highlighter::new(theme);
+        (
+            ParseState::new(syntax),
+            HighlightState::new(&highlighter, ScopeStack::new()),
+        )
+    }
+
+    pub fn reset(&mut self) {
+        self.states.truncate(1);
+        self.dirty_from = 0;
+        self.converge_after = 0;
+    }
+
+    pub fn invalidate(&mut self, line: usize) {
+        self.dirty_from = self.dirty_from.min(line);
+        self.converge_after = self.converge_after.max(line + 1);
+    }
+
+    pub fn insert_line(&mut self, line: usize) {
+        if line < self.states.len() {
+            let placeholder = self.states[line].clone();
+            self.states.insert(line, placeholder);
+        }
+        self.invalidate(line.saturating_sub(1));
+        self.converge_after = self.converge_after.max(line + 1);
+    }
+
+    pub fn remove_line(&mut self, line: usize) {
+        if line + 1 < self.states.len() {
+            self.states.remove(line + 1);
+        }
+        self.invalidate(line);
+    }
+
+    /// Brings `spans` up to date for every line before `end_line`.
+    pub fn highlight(&mut self, text: &Rope, spans: &mut Vec<Vec<CachedSpan>>, end_line: usize) {
+        let line_count = text.len_lines();
+        let end_line = end_line.min(line_count);
+        spans.resize(line_count, vec![]);
+        self.states.truncate(line_count + 1);
+        self.dirty_from = self.dirty_from.min(self.states.len() - 1);
+
+        let highlighter = Highlighter::new(&self.theme);
+        let color_support = self.color_support;
+        while self.dirty_from < end_line {
+            let line_idx = self.dirty_from;
+            let (mut parse_state, mut highlight_state) = self.states[line_idx].clone();
+            let line = text.line(line_idx).to_string();
+            let ops = parse_state
+                .parse_line(&line, &self.syntax_set)
+                .unwrap_or_default();
+            spans[line_idx] =
+                HighlightIterator::new(&mut highlight_state, &ops, &line, &highlighter)
+                    .map(|(style, content)| to_cached_span(style, content, color_support))
+                    .collect::<Vec<CachedSpan>>();
+
+            let next = (parse_state, highlight_state);
+            let next_idx = line_idx + 1;
+            if next_idx < self.states.len() {
+                let converged = next_idx >= self.converge_after && self.states[next_idx] == next;
+                self.states[next_idx] = next;
+                self.dirty_from = if converged {
+                    self.states.len() - 1
+                } else {
+                    next_idx
+                };
+            } else {
+                self.states.push(next);
+                self.dirty_from = next_idx;
+            }
+        }
+    }
+
+    /// Rebuilds the spans of lines `start_line..end_line` from tree-sitter highlight captures,
+    /// styling each capture with the theme's color for the matching TextMate scope.
+    pub fn highlight_tree(
+        &mut self,
+        text: &Rope,
+        tree: &SyntaxTree,
+        spans: &mut Vec<Vec<CachedSpan>>,
+        start_line: usize,
+        end_line: usize,
+    ) {
+        let line_count = text.len_lines();
+        spans.resize(line_count, vec![]);
+        let start_line = start_line.min(line_count);
+        let end_line = end_line.min(line_count);
+        if start_line >= end_line {
+            return;
+        }
+        let highlighter = Highlighter::new(&self.theme);
+        let color_support = self.color_support;
+        if self.capture_styles.len() != tree.highlight_query.capture_names().len() {
+            self.capture_styles = tree
+                .highlight_query
+                .capture_names()
+                .iter()
+                .map(|name| {
+                    let stack = Scope::new(capture_scope(name))
+                        .map(|scope| vec![scope])
+                        .unwrap_or_default();
+                    convert_style(highlighter.style_for_stack(&stack), color_support)
+                })
+                .collect();
+        }
+        let plain = convert_style(highlighter.style_for_stack(&[]), color_support);
+
+        let start_byte = text.line_to_byte(start_line);
+        let end_byte = text.line_to_byte(end_line);
+        let captures = tree.captures_in(text, start_byte..end_byte);
+        let lines = spans.iter_mut().enumerate().take(end_line).skip(start_line);
+        for (line_idx, line_spans) in lines {
+            let line_start = text.line_to_byte(line_idx);
+            let line = text.line(line_idx).to_string();
+            line_spans.clear();
+            for (offset, c) in line.char_indices() {
+                let style = captures[line_start + offset - start_byte]
+                    .and_then(|idx| self.capture_styles.get(idx as usize).copied())
+                    .unwrap_or(plain);
+                match line_spans.last_mut() {
+                    Some(span) if span.style == style => span.content.push(c),
+                    _ => line_spans.push(CachedSpan {
+                        content: c.to_string(),
+                        style,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// TextMate scope whose theme color a tree-sitter capture is drawn with.
+fn capture_scope(capture: &str) -> &'static str {
+    match capture {
+        "function.macro" => "entity.name.function.macro",
+        "function" | "function.method" => "entity.name.function",
+        "type.builtin" => "storage.type",
+        "type" | "constructor" => "entity.name.type",
+        "constant.builtin" => "constant.language",
+        "variable.parameter" => "variable.parameter",
+        "variable.builtin" => "variable.language",
+        "escape" => "constant.character.escape",
+        "operator" => "keyword.operator",
+        _ => match capture.split('.').next().unwrap_or_default() {
+            "comment" => "comment",
+            "string" => "string.quoted",
+            "keyword" => "keyword",
+            "constant" => "constant.other",
+            "property" => "variable.other.member",
+            "label" => "entity.name.label",
+            "attribute" => "entity.other.attribute-name",
+            "punctuation" => "punctuation",
+            _ => "source",
+        },
+    }
+}
+
+pub fn convert_style(style: SyntectStyle, color_support: ColorSupport) -> Style {
+    Style::default().fg(color_support.color(style.foreground))
+}
+
+pub fn to_cached_span(style: SyntectStyle, value: &str, color_support: ColorSupport) -> CachedSpan {
+    CachedSpan {
+        content: value.to_owned(),
+        style: convert_style(style, color_support),
+    }
+}
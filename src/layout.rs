@@ -0,0 +1,547 @@
+use anyhow::{Error as AnyHowError, Result as AnyHowResult};
+use ratatui::layout::{Direction, Rect};
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+/// A side of a window or of the whole screen, for `Ctrl-w h/j/k/l` and `Ctrl-w H/J/K/L`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+fn right(rect: &Rect) -> u16 {
+    rect.x.saturating_add(rect.width)
+}
+
+fn bottom(rect: &Rect) -> u16 {
+    rect.y.saturating_add(rect.height)
+}
+
+/// The window next to `current` towards `edge`. Of the windows touching that side, the one
+/// level with the cursor wins, so `Ctrl-w l` then `Ctrl-w h` comes back to the same window.
+pub fn neighbour(
+    areas: &[(Uuid, Rect)],
+    current: &Rect,
+    cursor: (u16, u16),
+    edge: Edge,
+) -> Option<Uuid> {
+    let (col, row) = cursor;
+    let beyond = |rect: &Rect| match edge {
+        Edge::Left => {
+            right(rect) <= current.x && rect.y < bottom(current) && current.y < bottom(rect)
+        }
+        Edge::Right => {
+            rect.x >= right(current) && rect.y < bottom(current) && current.y < bottom(rect)
+        }
+        Edge::Top => {
+            bottom(rect) <= current.y && rect.x < right(current) && current.x < right(rect)
+        }
+        Edge::Bottom => {
+            rect.y >= bottom(current) && rect.x < right(current) && current.x < right(rect)
+        }
+    };
+    let distance = |rect: &Rect| match edge {
+        Edge::Left => current.x - right(rect),
+        Edge::Right => rect.x - right(current),
+        Edge::Top => current.y - bottom(rect),
+        Edge::Bottom => rect.y - bottom(current),
+    };
+    let level = |rect: &Rect| match edge {
+        Edge::Left | Edge::Right => rect.y <= row && row < bottom(rect),
+        Edge::Top | Edge::Bottom => rect.x <= col && col < right(rect),
+    };
+    let candidates = areas
+        .iter()
+        .filter(|(_, rect)| rect != current && beyond(rect))
+        .collect::<Vec<&(Uuid, Rect)>>();
+    let nearest = candidates.iter().map(|(_, rect)| distance(rect)).min()?;
+    let adjacent = candidates
+        .into_iter()
+        .filter(|(_, rect)| distance(rect) == nearest)
+        .collect::<Vec<&(Uuid, Rect)>>();
+    adjacent
+        .iter()
+        .find(|(_, rect)| level(rect))
+        .or_else(|| adjacent.iter().min_by_key(|(_, rect)| (rect.y, rect.x)))
+        .map(|(id, _)| *id)
+}
+
+/// The window whose area holds the screen cell at (`col`, `row`).
+pub fn window_at(areas: &[(Uuid, Rect)], col: u16, row: u16) -> Option<Uuid> {
+    areas
+        .iter()
+        .find(|(_, rect)| rect.x <= col && col < right(rect) && rect.y <= row && row < bottom(rect))
+        .map(|(id, _)| *id)
+}
+
+/// The window a mouse drag from (`col`, `row`) resizes, with the direction it resizes in. A
+/// window's header row is its border with the window above and its first column the border
+/// with the window to its left; windows at the top or left of the screen have no border there.
+pub fn border_at(areas: &[(Uuid, Rect)], col: u16, row: u16) -> Option<(Uuid, Direction)> {
+    let id = window_at(areas, col, row)?;
+    let (_, area) = areas.iter().find(|(window_id, _)| *window_id == id)?;
+    if row == area.y {
+        neighbour(areas, area, (col, row), Edge::Top).map(|id| (id, Direction::Vertical))
+    } else if col == area.x {
+        neighbour(areas, area, (col, row), Edge::Left).map(|id| (id, Direction::Horizontal))
+    } else {
+        None
+    }
+}
+
+/// Smallest window the resize commands leave: a header, a text line and the borders.
+pub const MIN_HEIGHT: u16 = 4;
+/// Smallest window width the resize commands leave: the gutter and a few columns of text.
+pub const MIN_WIDTH: u16 = 12;
+
+pub fn size_along(rect: &Rect, direction: Direction) -> u16 {
+    match direction {
+        Direction::Vertical => rect.height,
+        Direction::Horizontal => rect.width,
+    }
+}
+
+pub fn min_size(direction: Direction) -> u16 {
+    match direction {
+        Direction::Vertical => MIN_HEIGHT,
+        Direction::Horizontal => MIN_WIDTH,
+    }
+}
+
+/// Sets `sizes[idx]` to `size`. The difference comes out of (or goes to) the following
+/// siblings first and then the preceding ones, as far as each can give without going under
+/// `min`.
+pub fn resize(sizes: &mut [u16], idx: usize, size: u16, min: u16) {
+    let total = sizes.iter().map(|s| *s as u32).sum::<u32>();
+    let others_min = min as u32 * (sizes.len() as u32 - 1);
+    let size = (size as u32)
+        .max(min as u32)
+        .min(total.saturating_sub(others_min));
+    let mut delta = size as i64 - sizes[idx] as i64;
+    sizes[idx] = size as u16;
+    let others = (idx + 1..sizes.len())
+        .chain((0..idx).rev())
+        .collect::<Vec<usize>>();
+    for i in others {
+        match delta.cmp(&0) {
+            Ordering::Greater => {
+                let take = (sizes[i].saturating_sub(min) as i64).min(delta);
+                sizes[i] -= take as u16;
+                delta -= take;
+            }
+            Ordering::Less => {
+                sizes[i] += (-delta) as u16;
+                delta = 0;
+            }
+            Ordering::Equal => {}
+        }
+    }
+    sizes[idx] = (sizes[idx] as i64 - delta) as u16;
+}
+
+/// `total` split into `count` sizes as equal as they can be.
+pub fn equal(total: u16, count: usize) -> Vec<u16> {
+    let count = count.max(1) as u16;
+    (0..count)
+        .map(|i| total / count + if i < total % count { 1 } else { 0 })
+        .collect()
+}
+
+/// Lays `sizes` out one after another inside `parent`.
+pub fn place(parent: &Rect, direction: Direction, sizes: &[u16]) -> Vec<Rect> {
+    let mut offset = 0;
+    sizes
+        .iter()
+        .map(|size| {
+            let rect = match direction {
+                Direction::Vertical => Rect::new(parent.x, parent.y + offset, parent.width, *size),
+                Direction::Horizontal => {
+                    Rect::new(parent.x + offset, parent.y, *size, parent.height)
+                }
+            };
+            offset += size;
+            rect
+        })
+        .collect()
+}
+
+/// The size `:resize {arg}` asks for: `N`, `+N` or `-N` relative to `current`, or as large as
+/// possible when `arg` is empty.
+pub fn resize_target(arg: &str, current: u16) -> AnyHowResult<u16> {
+    let arg = arg.trim();
+    let invalid = || AnyHowError::msg(format!("E475: Invalid argument: {}", arg));
+    if arg.is_empty() {
+        Ok(u16::MAX)
+    } else if let Some(n) = arg.strip_prefix('+') {
+        Ok(current.saturating_add(n.parse::<u16>().map_err(|_| invalid())?))
+    } else if let Some(n) = arg.strip_prefix('-') {
+        Ok(current.saturating_sub(n.parse::<u16>().map_err(|_| invalid())?))
+    } else {
+        arg.parse::<u16>().map_err(|_| invalid())
+    }
+}
+
+/// Splits `total` cells in proportion to `weights`. Boundaries are rounded rather than sizes,
+/// so the sizes always add up to `total`.
+pub fn proportional(total: u16, weights: &[f64]) -> Vec<u16> {
+    let sum = weights.iter().sum::<f64>();
+    if sum <= 0.0 {
+        return equal(total, weights.len());
+    }
+    let mut covered = 0.0;
+    let mut start = 0;
+    weights
+        .iter()
+        .map(|weight| {
+            covered += weight;
+            let end = (total as f64 * covered / sum).round() as u16;
+            let size = end.saturating_sub(start);
+            start = end;
+            size
+        })
+        .collect()
+}
+
+/// A node of the window layout: a window, or a split sharing its area between its children
+/// along `direction` in proportion to `weights`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayoutNode {
+    Window(Uuid),
+    Split {
+        direction: Direction,
+        weights: Vec<f64>,
+        children: Vec<LayoutNode>,
+    },
+}
+
+impl LayoutNode {
+    pub fn contains(&self, window_id: Uuid) -> bool {
+        match self {
+            LayoutNode::Window(id) => *id == window_id,
+            LayoutNode::Split { children, .. } => children.iter().any(|c| c.contains(window_id)),
+        }
+    }
+
+    fn windows(&self, windows: &mut Vec<Uuid>) {
+        match self {
+            LayoutNode::Window(id) => windows.push(*id),
+            LayoutNode::Split { children, .. } => children.iter().for_each(|c| c.windows(windows)),
+        }
+    }
+
+    fn areas(&self, area: Rect, areas: &mut Vec<(Uuid, Rect)>) {
+        match self {
+            LayoutNode::Window(id) => areas.push((*id, area)),
+            LayoutNode::Split {
+                direction,
+                weights,
+                children,
+            } => {
+                let sizes = proportional(size_along(&area, *direction), weights);
+                for (child, rect) in children.iter().zip(place(&area, *direction, &sizes)) {
+                    child.areas(rect, areas);
+                }
+            }
+        }
+    }
+
+    fn split(&mut self, target: Uuid, window_id: Uuid, direction: Direction) -> bool {
+        match self {
+            LayoutNode::Window(id) => {
+                if *id != target {
+                    return false;
+                }
+                *self = LayoutNode::Split {
+                    direction,
+                    weights: vec![1.0, 1.0],
+                    children: vec![LayoutNode::Window(target), LayoutNode::Window(window_id)],
+                };
+                true
+            }
+            LayoutNode::Split {
+                direction: split_direction,
+                weights,
+                children,
+            } => {
+                let idx = children
+                    .iter()
+                    .position(|c| *c == LayoutNode::Window(target));
+                if let (Some(idx), true) = (idx, *split_direction == direction) {
+                    weights[idx] /= 2.0;
+                    weights.insert(idx + 1, weights[idx]);
+                    children.insert(idx + 1, LayoutNode::Window(window_id));
+                    return true;
+                }
+                children
+                    .iter_mut()
+                    .any(|c| c.split(target, window_id, direction))
+            }
+        }
+    }
+
+    fn remove(&mut self, target: Uuid) -> bool {
+        let (weights, children) = match self {
+            LayoutNode::Split {
+                weights, children, ..
+            } => (weights, children),
+            LayoutNode::Window(_) => return false,
+        };
+        match children
+            .iter()
+            .position(|c| *c == LayoutNode::Window(target))
+        {
+            Some(idx) => {
+                let weight = weights.remove(idx);
+                children.remove(idx);
+                // The window after it takes the space, or the one before when it was last.
+                let heir = idx.min(children.len() - 1);
+                weights[heir] += weight;
+            }
+            None => {
+                if !children.iter_mut().any(|c| c.remove(target)) {
+                    return false;
+                }
+            }
+        }
+        self.normalize();
+        true
+    }
+
+    /// Replaces a split left with one child by that child, and merges child splits running
+    /// the same way as this one into it.
+    fn normalize(&mut self) {
+        if let LayoutNode::Split { children, .. } = self {
+            if children.len() == 1 {
+                let only = children.remove(0);
+                *self = only;
+                return;
+            }
+        }
+        if let LayoutNode::Split {
+            direction,
+            weights,
+            children,
+        } = self
+        {
+            let mut merged_weights = vec![];
+            let mut merged_children = vec![];
+            for (weight, child) in weights.drain(..).zip(children.drain(..)) {
+                match child {
+                    LayoutNode::Split {
+                        direction: child_direction,
+                        weights: child_weights,
+                        children: grandchildren,
+                    } if child_direction == *direction => {
+                        let sum = child_weights.iter().sum::<f64>();
+                        merged_weights.extend(child_weights.iter().map(|w| weight * w / sum));
+                        merged_children.extend(grandchildren);
+                    }
+                    child => {
+                        merged_weights.push(weight);
+                        merged_children.push(child);
+                    }
+                }
+            }
+            *weights = merged_weights;
+            *children = merged_children;
+        }
+    }
+
+    /// The split holding `target` directly.
+    fn parent_of(&mut self, target: Uuid) -> Option<&mut LayoutNode> {
+        let is_parent = match self {
+            LayoutNode::Split { children, .. } => children.contains(&LayoutNode::Window(target)),
+            LayoutNode::Window(_) => false,
+        };
+        if is_parent {
+            return Some(self);
+        }
+        match self {
+            LayoutNode::Split { children, .. } => {
+                children.iter_mut().find_map(|c| c.parent_of(target))
+            }
+            LayoutNode::Window(_) => None,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        area: Rect,
+        target: Uuid,
+        direction: Direction,
+        size: &dyn Fn(u16) -> u16,
+    ) -> bool {
+        if let LayoutNode::Split {
+            direction: split_direction,
+            weights,
+            children,
+        } = self
+        {
+            let mut sizes = proportional(size_along(&area, *split_direction), weights);
+            let rects = place(&area, *split_direction, &sizes);
+            let idx = match children.iter().position(|c| c.contains(target)) {
+                Some(idx) => idx,
+                None => return false,
+            };
+            // The split nearest the window wins, so look further down first.
+            if children[idx].resize(rects[idx], target, direction, size) {
+                return true;
+            }
+            if *split_direction == direction {
+                let target_size = size(sizes[idx]);
+                resize(&mut sizes, idx, target_size, min_size(direction));
+                *weights = sizes.iter().map(|s| *s as f64).collect();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn equalize(&mut self) {
+        if let LayoutNode::Split {
+            weights, children, ..
+        } = self
+        {
+            weights.iter_mut().for_each(|w| *w = 1.0);
+            children.iter_mut().for_each(|c| c.equalize());
+        }
+    }
+}
+
+/// The windows of a tab page, arranged by nested splits. Areas aren't stored: every window's
+/// `Rect` is worked out from the root, so splitting, closing, moving and terminal resizes
+/// all leave the windows tiling the screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowLayout {
+    pub root: LayoutNode,
+}
+
+impl WindowLayout {
+    pub fn new(window_id: Uuid) -> Self {
+        Self {
+            root: LayoutNode::Window(window_id),
+        }
+    }
+
+    /// Windows in layout order, left to right and top to bottom.
+    pub fn windows(&self) -> Vec<Uuid> {
+        let mut windows = vec![];
+        self.root.windows(&mut windows);
+        windows
+    }
+
+    pub fn areas(&self, area: Rect) -> Vec<(Uuid, Rect)> {
+        let mut areas = vec![];
+        self.root.areas(area, &mut areas);
+        areas
+    }
+
+    /// Splits `target` along `direction`, putting `window_id` after it with half its space.
+    pub fn split(&mut self, target: Uuid, window_id: Uuid, direction: Direction) -> bool {
+        self.root.split(target, window_id, direction)
+    }
+
+    /// Takes `target` out of the layout; its space goes to the window beside it. The last
+    /// window can't be removed.
+    pub fn remove(&mut self, target: Uuid) -> bool {
+        self.root.remove(target)
+    }
+
+    /// Swaps `target` with the next window in its split, or the previous one when it is last,
+    /// returning the window it swapped with.
+    pub fn exchange(&mut self, target: Uuid) -> Option<Uuid> {
+        if let Some(LayoutNode::Split { children, .. }) = self.root.parent_of(target) {
+            let idx = children
+                .iter()
+                .position(|c| *c == LayoutNode::Window(target))?;
+            let other = if idx + 1 < children.len() {
+                idx + 1
+            } else {
+                idx.checked_sub(1)?
+            };
+            if let LayoutNode::Window(other_id) = children[other] {
+                children.swap(idx, other);
+                return Some(other_id);
+            }
+        }
+        None
+    }
+
+    /// Rotates the windows of `target`'s split, leaving their sizes where they were.
+    pub fn rotate(&mut self, target: Uuid, downwards: bool) -> AnyHowResult<()> {
+        if let Some(LayoutNode::Split { children, .. }) = self.root.parent_of(target) {
+            if children
+                .iter()
+                .any(|c| matches!(c, LayoutNode::Split { .. }))
+            {
+                return Err(AnyHowError::msg(
+                    "E443: Cannot rotate when another window is split",
+                ));
+            }
+            if downwards {
+                children.rotate_right(1);
+            } else {
+                children.rotate_left(1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves `target` to take up the whole of one side of the screen.
+    pub fn move_to_edge(&mut self, target: Uuid, edge: Edge) {
+        if !self.remove(target) {
+            return;
+        }
+        let (direction, first) = match edge {
+            Edge::Left => (Direction::Horizontal, true),
+            Edge::Right => (Direction::Horizontal, false),
+            Edge::Top => (Direction::Vertical, true),
+            Edge::Bottom => (Direction::Vertical, false),
+        };
+        let window = LayoutNode::Window(target);
+        match &mut self.root {
+            LayoutNode::Split {
+                direction: root_direction,
+                weights,
+                children,
+            } if *root_direction == direction => {
+                let weight = weights.iter().sum::<f64>() / weights.len() as f64;
+                let idx = if first { 0 } else { children.len() };
+                weights.insert(idx, weight);
+                children.insert(idx, window);
+            }
+            root => {
+                let rest = root.clone();
+                *root = LayoutNode::Split {
+                    direction,
+                    weights: vec![1.0, 1.0],
+                    children: if first {
+                        vec![window, rest]
+                    } else {
+                        vec![rest, window]
+                    },
+                };
+            }
+        }
+    }
+
+    /// Resizes `target` along `direction` to whatever `size` makes of its current size, by
+    /// moving the boundaries of the nearest split running that way.
+    pub fn resize(
+        &mut self,
+        area: Rect,
+        target: Uuid,
+        direction: Direction,
+        size: &dyn Fn(u16) -> u16,
+    ) -> bool {
+        self.root.resize(area, target, direction, size)
+    }
+
+    /// Gives every child of every split an equal share.
+    pub fn equalize(&mut self) {
+        self.root.equalize();
+    }
+}
@@ -1,8 +1,22 @@
 pub mod app;
+pub mod block;
 pub mod buffer;
+pub mod buffer_list;
+pub mod diff;
+pub mod encoding;
+pub mod file;
+pub mod filetype;
+pub mod fold;
+pub mod highlight;
+pub mod layout;
 pub mod lsp;
+pub mod options;
 pub mod parser;
 pub mod reflow;
+pub mod swap;
+pub mod syntax_tree;
+pub mod tab;
+pub mod theme;
 pub mod token;
 pub mod ui;
 pub mod window;
@@ -23,20 +37,40 @@ use log4rs::{
 };
 extern crate log;
 
-fn add_safe(n: u16, other: u16) -> u16 {
-    (n).checked_add(other).unwrap_or(n)
+trait CheckedOps: Copy {
+    fn add_checked(self, other: Self) -> Option<Self>;
+    fn sub_checked(self, other: Self) -> Option<Self>;
 }
 
-fn sub_safe(n: u16, other: u16) -> u16 {
-    (n).checked_sub(other).unwrap_or(n)
+macro_rules! impl_checked_ops {
+    ($($t:ty),*) => {
+        $(impl CheckedOps for $t {
+            fn add_checked(self, other: Self) -> Option<Self> {
+                self.checked_add(other)
+            }
+            fn sub_checked(self, other: Self) -> Option<Self> {
+                self.checked_sub(other)
+            }
+        })*
+    };
 }
 
-fn mut_add_safe(n: &mut u16, other: u16) {
-    *n = (*n).checked_add(other).unwrap_or(*n);
+impl_checked_ops!(u16, usize);
+
+fn add_safe<T: CheckedOps>(n: T, other: T) -> T {
+    n.add_checked(other).unwrap_or(n)
+}
+
+fn sub_safe<T: CheckedOps>(n: T, other: T) -> T {
+    n.sub_checked(other).unwrap_or(n)
+}
+
+fn mut_add_safe<T: CheckedOps>(n: &mut T, other: T) {
+    *n = (*n).add_checked(other).unwrap_or(*n);
 }
 
-fn mut_sub_safe(n: &mut u16, other: u16) {
-    *n = (*n).checked_sub(other).unwrap_or(*n);
+fn mut_sub_safe<T: CheckedOps>(n: &mut T, other: T) {
+    *n = (*n).sub_checked(other).unwrap_or(*n);
 }
 
 #[derive(Debug, FromArgs)]
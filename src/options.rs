@@ -0,0 +1,146 @@
+use crate::fold::FoldMethod;
+use anyhow::{Error as AnyHowError, Result as AnyHowResult};
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub backup: bool,
+    pub swapfile: bool,
+    pub updatetime: u64,
+    pub autoread: bool,
+    pub checkinterval: u64,
+    pub largefile: u64,
+    pub cursorline: bool,
+    pub foldcolumn: bool,
+    pub scrolloff: usize,
+    pub hidden: bool,
+    pub mouse: bool,
+}
+
+/// Options local to a buffer, seeded from its filetype's defaults.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferOptions {
+    pub tabstop: usize,
+    pub shiftwidth: usize,
+    pub expandtab: bool,
+    pub treesitter: bool,
+    pub foldmethod: FoldMethod,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            tabstop: 8,
+            shiftwidth: 8,
+            expandtab: false,
+            treesitter: false,
+            foldmethod: FoldMethod::default(),
+        }
+    }
+}
+
+impl BufferOptions {
+    pub fn set(&mut self, arg: &str) -> Option<AnyHowResult<()>> {
+        let (name, value) = split_option(arg);
+        let result = match (name, value) {
+            ("tabstop" | "ts", Some(value)) => parse_number(name, value).map(|n| self.tabstop = n),
+            ("shiftwidth" | "sw", Some(value)) => {
+                parse_number(name, value).map(|n| self.shiftwidth = n)
+            }
+            ("expandtab" | "et", None) => {
+                self.expandtab = true;
+                Ok(())
+            }
+            ("noexpandtab" | "noet", None) => {
+                self.expandtab = false;
+                Ok(())
+            }
+            ("treesitter", None) => {
+                self.treesitter = true;
+                Ok(())
+            }
+            ("notreesitter", None) => {
+                self.treesitter = false;
+                Ok(())
+            }
+            ("foldmethod" | "fdm", Some(value)) => {
+                FoldMethod::parse(value).map(|m| self.foldmethod = m)
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+pub const DEFAULT_LARGE_FILE_BYTES: u64 = 100 * 1024 * 1024;
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            backup: false,
+            swapfile: true,
+            updatetime: 4000,
+            autoread: false,
+            checkinterval: 0,
+            largefile: DEFAULT_LARGE_FILE_BYTES / (1024 * 1024),
+            cursorline: false,
+            foldcolumn: true,
+            scrolloff: 0,
+            hidden: false,
+            mouse: true,
+        }
+    }
+}
+
+pub const UNKNOWN_OPTION_ERR: &str = "E518: Unknown option";
+
+impl Options {
+    pub fn large_file_bytes(&self) -> u64 {
+        self.largefile.saturating_mul(1024 * 1024)
+    }
+
+    pub fn set(&mut self, arg: &str) -> AnyHowResult<()> {
+        let (name, value) = split_option(arg);
+        match (name, value) {
+            ("backup" | "bk", None) => self.backup = true,
+            ("nobackup" | "nobk", None) => self.backup = false,
+            ("swapfile" | "swf", None) => self.swapfile = true,
+            ("noswapfile" | "noswf", None) => self.swapfile = false,
+            ("updatetime" | "ut", Some(value)) => self.updatetime = parse_number(name, value)?,
+            ("autoread" | "ar", None) => self.autoread = true,
+            ("noautoread" | "noar", None) => self.autoread = false,
+            ("checkinterval", Some(value)) => self.checkinterval = parse_number(name, value)?,
+            ("largefile", Some(value)) => self.largefile = parse_number(name, value)?,
+            ("cursorline" | "cul", None) => self.cursorline = true,
+            ("nocursorline" | "nocul", None) => self.cursorline = false,
+            ("foldcolumn" | "fdc", None) => self.foldcolumn = true,
+            ("nofoldcolumn" | "nofdc", None) => self.foldcolumn = false,
+            ("scrolloff" | "so", Some(value)) => self.scrolloff = parse_number(name, value)?,
+            ("hidden" | "hid", None) => self.hidden = true,
+            ("nohidden" | "nohid", None) => self.hidden = false,
+            ("mouse", None) => self.mouse = true,
+            ("nomouse", None) => self.mouse = false,
+            _ => {
+                return Err(AnyHowError::msg(format!(
+                    "{}: {}",
+                    UNKNOWN_OPTION_ERR,
+                    arg.trim()
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> AnyHowResult<T> {
+    value
+        .parse::<T>()
+        .map_err(|_| AnyHowError::msg(format!("E521: Number required after =: {}={}", name, value)))
+}
+
+pub fn split_option(arg: &str) -> (&str, Option<&str>) {
+    let arg = arg.trim();
+    match arg.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(value.trim())),
+        None => (arg, None),
+    }
+}
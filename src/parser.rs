@@ -1,6 +1,6 @@
 use crate::app::Mode;
-use crate::token::{get_token_from_key, get_tokens_from_chars, Token};
-use crossterm::event::{KeyCode, KeyEvent as Key};
+use crate::token::{get_token_from_key, get_tokens_from_chars, normal_token::WINDOW_PREFIX, Token};
+use crossterm::event::{KeyCode, KeyEvent as Key, KeyModifiers};
 
 pub struct Parser {
     pub token_str: Vec<char>,
@@ -17,16 +17,31 @@ impl Parser {
 
     pub fn handle_event(&mut self, msg: UserInput, mode: &Mode) -> Vec<Token> {
         let event = msg.event;
-        if let Ok(token) = get_token_from_key(mode, &event) {
+        let control = event.modifiers.contains(KeyModifiers::CONTROL);
+        let window_command = *mode == Mode::Normal && self.token_str.last() == Some(&WINDOW_PREFIX);
+        if *mode == Mode::Normal && control && event.code == KeyCode::Char('w') && !window_command {
+            self.token_str.push(WINDOW_PREFIX);
+            return vec![];
+        }
+        if let (false, Ok(token)) = (window_command, get_token_from_key(mode, &event)) {
             return vec![token];
         } else if let KeyCode::Char(c) = event.code {
+            // `Ctrl-w Ctrl-h` is the same command as `Ctrl-w h`.
+            let c = if window_command && control {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
             self.token_str.push(c);
             let tokens = get_tokens_from_chars(mode, &self.token_str);
-            if !tokens.is_empty()
+            if window_command && tokens.is_empty() {
+                self.token_str.truncate(0);
+            } else if !tokens.is_empty()
                 && tokens
                     .iter()
                     .filter(|t| match t {
-                        Token::Operator(_) => false,
+                        // Visual mode already has the text an operator applies to.
+                        Token::Operator(_) => *mode == Mode::Visual,
                         _ => true,
                     })
                     .count()
@@ -35,6 +50,8 @@ impl Parser {
                 self.token_str.truncate(0);
                 return tokens;
             }
+        } else if window_command {
+            self.token_str.truncate(0);
         }
         vec![]
     }
@@ -0,0 +1,109 @@
+use crate::file;
+use ropey::Rope;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+const SWAP_MAGIC: &str = "ri-swap 1";
+const HEADER_END: &str = "---";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapInfo {
+    pub pid: u32,
+    pub file_path: String,
+    pub running: bool,
+}
+
+pub fn swap_path(file_path: &str) -> PathBuf {
+    let path = Path::new(file_path);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.swp", name))
+}
+
+pub fn write(swap_path: &Path, file_path: &str, text: &Rope) -> io::Result<()> {
+    file::write_atomic(swap_path, false, |writer| {
+        write!(
+            writer,
+            "{}\npid={}\npath={}\n{}\n",
+            SWAP_MAGIC,
+            std::process::id(),
+            file_path,
+            HEADER_END
+        )?;
+        text.write_to(writer)
+    })
+}
+
+pub fn read_info(swap_path: &Path) -> Option<SwapInfo> {
+    let mut reader = BufReader::new(File::open(swap_path).ok()?);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    if line.trim_end() != SWAP_MAGIC {
+        return None;
+    }
+    let mut pid = None;
+    let mut file_path = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 || line.trim_end() == HEADER_END {
+            break;
+        }
+        match line.trim_end().split_once('=') {
+            Some(("pid", value)) => pid = value.parse::<u32>().ok(),
+            Some(("path", value)) => file_path = value.to_owned(),
+            _ => (),
+        }
+    }
+    let pid = pid?;
+    Some(SwapInfo {
+        pid,
+        file_path,
+        running: process_running(pid),
+    })
+}
+
+pub fn read_contents(swap_path: &Path) -> io::Result<Rope> {
+    let mut reader = BufReader::new(File::open(swap_path)?);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "E307: Swap file is corrupt",
+            ));
+        }
+        if line.trim_end() == HEADER_END {
+            break;
+        }
+    }
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    Ok(Rope::from_str(&contents))
+}
+
+pub fn remove(swap_path: &Path) {
+    let _ = fs::remove_file(swap_path);
+}
+
+pub fn attention_message(swap_path: &Path, info: &SwapInfo) -> String {
+    format!(
+        "E325: ATTENTION Found a swap file \"{}\" owned by PID {}{}. Another instance may be editing this file; use :recover to restore it",
+        swap_path.display(),
+        info.pid,
+        if info.running { " (still running)" } else { "" }
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn process_running(pid: u32) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_running(_pid: u32) -> bool {
+    false
+}
@@ -0,0 +1,382 @@
+use ropey::Rope;
+use std::ops::Range;
+use std::sync::Arc;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
+
+/// Grammars compiled into the binary, with their highlight queries, keyed by filetype.
+pub fn language_for(filetype: &str) -> Option<(Language, &'static str)> {
+    match filetype {
+        "rust" => Some((
+            tree_sitter_rust::language(),
+            tree_sitter_rust::HIGHLIGHT_QUERY,
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextObject {
+    Function,
+    Class,
+    Argument,
+}
+
+/// Node kinds that make up each text object in a grammar.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextObjectKinds {
+    pub functions: &'static [&'static str],
+    pub classes: &'static [&'static str],
+    pub argument_lists: &'static [&'static str],
+}
+
+pub fn text_object_kinds(filetype: &str) -> TextObjectKinds {
+    match filetype {
+        "rust" => TextObjectKinds {
+            functions: &[
+                "function_item",
+                "function_signature_item",
+                "closure_expression",
+            ],
+            classes: &[
+                "impl_item",
+                "struct_item",
+                "enum_item",
+                "trait_item",
+                "union_item",
+                "mod_item",
+            ],
+            argument_lists: &[
+                "parameters",
+                "arguments",
+                "type_arguments",
+                "type_parameters",
+                "closure_parameters",
+                "tuple_expression",
+            ],
+        },
+        _ => TextObjectKinds::default(),
+    }
+}
+
+/// A tree-sitter parse of a buffer. `update` edits the previous tree by the span that changed
+/// since it was parsed, so tree-sitter only re-lexes around the edit.
+pub struct SyntaxTree {
+    pub language: Language,
+    pub highlight_query: Arc<Query>,
+    pub kinds: TextObjectKinds,
+    parser: Parser,
+    tree: Option<Tree>,
+    source: Rope,
+    tick: usize,
+}
+
+impl Clone for SyntaxTree {
+    fn clone(&self) -> Self {
+        let mut parser = Parser::new();
+        let _ = parser.set_language(self.language);
+        Self {
+            language: self.language,
+            highlight_query: self.highlight_query.clone(),
+            kinds: self.kinds,
+            parser,
+            tree: self.tree.clone(),
+            source: self.source.clone(),
+            tick: self.tick,
+        }
+    }
+}
+
+impl SyntaxTree {
+    pub fn new(filetype: &str) -> Option<Self> {
+        let (language, highlights) = language_for(filetype)?;
+        let mut parser = Parser::new();
+        parser.set_language(language).ok()?;
+        let query = Query::new(language, highlights).ok()?;
+        Some(Self {
+            language,
+            highlight_query: Arc::new(query),
+            kinds: text_object_kinds(filetype),
+            parser,
+            tree: None,
+            source: Rope::new(),
+            tick: 0,
+        })
+    }
+
+    pub fn tree(&self) -> Option<&Tree> {
+        self.tree.as_ref()
+    }
+
+    pub fn update(&mut self, text: &Rope, changed_tick: usize) {
+        if self.tree.is_some() && self.tick == changed_tick {
+            return;
+        }
+        if let (Some(tree), Some(edit)) = (self.tree.as_mut(), input_edit(&self.source, text)) {
+            tree.edit(&edit);
+        }
+        self.tree = self
+            .parser
+            .parse_with(&mut |byte, _| read_chunk(text, byte), self.tree.as_ref());
+        self.source = text.clone();
+        self.tick = changed_tick;
+    }
+
+    /// The highlight capture covering each byte of `range`. Where captures nest the innermost
+    /// node wins; where one node is captured twice the earlier pattern wins.
+    pub fn captures_in(&self, text: &Rope, range: Range<usize>) -> Vec<Option<u32>> {
+        let mut captures = vec![None; range.len()];
+        let tree = match &self.tree {
+            Some(tree) => tree,
+            None => return captures,
+        };
+        let mut owners: Vec<Option<Range<usize>>> = vec![None; range.len()];
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(range.clone());
+        let provider = |node: Node| {
+            let bytes = node.byte_range();
+            text.slice(text.byte_to_char(bytes.start)..text.byte_to_char(bytes.end))
+                .chunks()
+                .map(|chunk| chunk.as_bytes())
+        };
+        for (query_match, idx) in cursor.captures(&self.highlight_query, tree.root_node(), provider)
+        {
+            let capture = query_match.captures[idx];
+            let node = capture.node.byte_range();
+            for byte in node.start.max(range.start)..node.end.min(range.end) {
+                let slot = byte - range.start;
+                let nested = match &owners[slot] {
+                    Some(owner) => {
+                        *owner != node && owner.start <= node.start && node.end <= owner.end
+                    }
+                    None => true,
+                };
+                if nested {
+                    captures[slot] = Some(capture.index);
+                    owners[slot] = Some(node.clone());
+                }
+            }
+        }
+        captures
+    }
+}
+
+impl SyntaxTree {
+    fn innermost<'t>(&'t self, start: usize, end: usize, kinds: &[&str]) -> Option<Node<'t>> {
+        let mut node = self
+            .tree
+            .as_ref()?
+            .root_node()
+            .descendant_for_byte_range(start, end)?;
+        loop {
+            if kinds.contains(&node.kind()) {
+                return Some(node);
+            }
+            node = node.parent()?;
+        }
+    }
+
+    /// Byte range of the text object around `byte`. Inner function and class objects are the
+    /// contents of their body between the braces; an inner argument is the argument itself and
+    /// the outer one adds its separating comma.
+    pub fn text_object(
+        &self,
+        byte: usize,
+        object: TextObject,
+        inner: bool,
+    ) -> Option<Range<usize>> {
+        match object {
+            TextObject::Function | TextObject::Class => {
+                let kinds = if object == TextObject::Function {
+                    self.kinds.functions
+                } else {
+                    self.kinds.classes
+                };
+                let node = self.innermost(byte, byte, kinds)?;
+                if !inner {
+                    return Some(node.byte_range());
+                }
+                let body = node.child_by_field_name("body")?;
+                let range = body.byte_range();
+                let delimited =
+                    body.child_count() > 1 && body.child(0).map(|c| c.kind()) == Some("{");
+                if delimited {
+                    Some(range.start + 1..range.end.saturating_sub(1).max(range.start + 1))
+                } else {
+                    Some(range)
+                }
+            }
+            TextObject::Argument => {
+                let list = self.innermost(byte, byte, self.kinds.argument_lists)?;
+                let mut cursor = list.walk();
+                let arguments = list
+                    .named_children(&mut cursor)
+                    .filter(|n| n.kind() != "comment")
+                    .collect::<Vec<Node>>();
+                let position = arguments
+                    .iter()
+                    .position(|n| byte < n.end_byte())
+                    .unwrap_or(arguments.len().checked_sub(1)?);
+                let argument = arguments[position];
+                if inner {
+                    return Some(argument.byte_range());
+                }
+                match (arguments.get(position + 1), position.checked_sub(1)) {
+                    (Some(next), _) => Some(argument.start_byte()..next.start_byte()),
+                    (None, Some(previous)) => {
+                        Some(arguments[previous].end_byte()..argument.end_byte())
+                    }
+                    (None, None) => Some(argument.byte_range()),
+                }
+            }
+        }
+    }
+
+    /// Start of the nearest function definition after (or before) `byte`.
+    pub fn function_start(&self, byte: usize, forward: bool) -> Option<usize> {
+        let tree = self.tree.as_ref()?;
+        let mut starts = vec![];
+        let mut cursor = tree.walk();
+        loop {
+            let node = cursor.node();
+            if self.kinds.functions.contains(&node.kind()) {
+                starts.push(node.start_byte());
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    starts.sort_unstable();
+                    return if forward {
+                        starts.into_iter().find(|s| *s > byte)
+                    } else {
+                        starts.into_iter().rev().find(|s| *s < byte)
+                    };
+                }
+            }
+        }
+    }
+
+    /// The smallest node strictly larger than `range` that contains it, for growing a selection
+    /// one syntactic level at a time.
+    pub fn parent_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        let mut node = self
+            .tree
+            .as_ref()?
+            .root_node()
+            .descendant_for_byte_range(range.start, range.end)?;
+        while node.byte_range() == range
+            || node.start_byte() > range.start
+            || node.end_byte() < range.end
+        {
+            node = node.parent()?;
+        }
+        Some(node.byte_range())
+    }
+}
+
+fn read_chunk(text: &Rope, byte: usize) -> &[u8] {
+    if byte >= text.len_bytes() {
+        return &[];
+    }
+    let (chunk, chunk_start, _, _) = text.chunk_at_byte(byte);
+    &chunk.as_bytes()[byte - chunk_start..]
+}
+
+fn is_continuation(text: &Rope, byte: usize) -> bool {
+    byte < text.len_bytes() && text.byte(byte) & 0xC0 == 0x80
+}
+
+fn point_at(text: &Rope, byte: usize) -> Point {
+    let row = text.byte_to_line(byte);
+    Point::new(row, byte - text.line_to_byte(row))
+}
+
+/// Length of the run of bytes two chunk streams share, up to `limit`. Leaves an edit did not
+/// touch stay shared between a rope and the clone it was edited from, so chunks at the same
+/// address are skipped without reading them and only the chunks around the edit are compared.
+fn shared_len<'a>(
+    mut old: impl Iterator<Item = &'a str>,
+    mut new: impl Iterator<Item = &'a str>,
+    backwards: bool,
+    limit: usize,
+) -> usize {
+    let (mut a, mut b): (&[u8], &[u8]) = (&[], &[]);
+    let mut shared = 0;
+    while shared < limit {
+        if a.is_empty() {
+            match old.next() {
+                Some(chunk) => a = chunk.as_bytes(),
+                None => break,
+            }
+            continue;
+        }
+        if b.is_empty() {
+            match new.next() {
+                Some(chunk) => b = chunk.as_bytes(),
+                None => break,
+            }
+            continue;
+        }
+        let len = a.len().min(b.len());
+        let (a_part, b_part) = if backwards {
+            (&a[a.len() - len..], &b[b.len() - len..])
+        } else {
+            (&a[..len], &b[..len])
+        };
+        let same = if a_part.as_ptr() == b_part.as_ptr() {
+            len
+        } else if backwards {
+            let pairs = a_part.iter().rev().zip(b_part.iter().rev());
+            pairs.take_while(|(x, y)| x == y).count()
+        } else {
+            let pairs = a_part.iter().zip(b_part.iter());
+            pairs.take_while(|(x, y)| x == y).count()
+        };
+        shared += same;
+        if same < len {
+            break;
+        }
+        if backwards {
+            a = &a[..a.len() - len];
+            b = &b[..b.len() - len];
+        } else {
+            a = &a[len..];
+            b = &b[len..];
+        }
+    }
+    shared.min(limit)
+}
+
+/// The single edit turning `old` into `new`: everything between their common prefix and
+/// common suffix, widened to character boundaries.
+pub fn input_edit(old: &Rope, new: &Rope) -> Option<InputEdit> {
+    let (old_len, new_len) = (old.len_bytes(), new.len_bytes());
+    let mut start = shared_len(old.chunks(), new.chunks(), false, old_len.min(new_len));
+    if start == old_len && start == new_len {
+        return None;
+    }
+    while start > 0 && (is_continuation(old, start) || is_continuation(new, start)) {
+        start -= 1;
+    }
+
+    let mut suffix = shared_len(
+        old.chunks_at_byte(old_len).0.reversed(),
+        new.chunks_at_byte(new_len).0.reversed(),
+        true,
+        (old_len - start).min(new_len - start),
+    );
+    while suffix > 0 && is_continuation(old, old_len - suffix) {
+        suffix -= 1;
+    }
+
+    let (old_end, new_end) = (old_len - suffix, new_len - suffix);
+    Some(InputEdit {
+        start_byte: start,
+        old_end_byte: old_end,
+        new_end_byte: new_end,
+        start_position: point_at(old, start),
+        old_end_position: point_at(old, old_end),
+        new_end_position: point_at(new, new_end),
+    })
+}
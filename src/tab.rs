@@ -1,7 +1,63 @@
+use crate::{layout::WindowLayout, window::Window};
+use anyhow::{Error as AnyHowError, Result as AnyHowResult};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// A tab page in the background. The app keeps the shown tab's windows and layout in its own
+/// fields and only packs them into a `Tab` while another tab is shown.
 pub struct Tab {
-    pub current_window: u8,
-    pub windows: Vec<Window>,
+    pub current_window: Uuid,
+    pub previous_window: Option<Uuid>,
+    pub windows: HashMap<Uuid, Window>,
+    pub layout: WindowLayout,
 }
 
 impl Tab {
+    pub fn current(&self) -> Option<&Window> {
+        self.windows.get(&self.current_window)
+    }
+}
+
+/// A tab's entry in the tab line, e.g. ` 2+ app.rs `: the number of windows when there is more
+/// than one, `+` when the current buffer is modified, then its name.
+pub fn label(title: &str, modified: bool, windows: usize) -> String {
+    let mut prefix = String::new();
+    if windows > 1 {
+        prefix.push_str(&windows.to_string());
+    }
+    if modified {
+        prefix.push('+');
+    }
+    if prefix.is_empty() {
+        format!(" {} ", title)
+    } else {
+        format!(" {} {} ", prefix, title)
+    }
+}
+
+/// Where `:tabmove {arg}` puts the tab at `current` among `count` tabs. No argument moves it
+/// last, `N` after the Nth tab (`0` first) and `+N`/`-N` that many places along.
+pub fn move_target(arg: &str, current: usize, count: usize) -> AnyHowResult<usize> {
+    let arg = arg.trim();
+    let invalid = || AnyHowError::msg(format!("E474: Invalid argument: {}", arg));
+    let last = count.saturating_sub(1);
+    let relative = |n: &str| {
+        if n.is_empty() {
+            Ok(1)
+        } else {
+            n.parse::<usize>().map_err(|_| invalid())
+        }
+    };
+    if arg.is_empty() || arg == "$" {
+        Ok(last)
+    } else if let Some(n) = arg.strip_prefix('+') {
+        Some(current + relative(n)?)
+            .filter(|target| *target <= last)
+            .ok_or_else(invalid)
+    } else if let Some(n) = arg.strip_prefix('-') {
+        current.checked_sub(relative(n)?).ok_or_else(invalid)
+    } else {
+        let n = arg.parse::<usize>().map_err(|_| invalid())?;
+        Ok(if n > current { n - 1 } else { n }.min(last))
+    }
 }
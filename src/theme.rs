@@ -0,0 +1,165 @@
+use ratatui::style::{Color, Style};
+use std::env;
+use std::path::PathBuf;
+use syntect::highlighting::{Color as SyntectColor, Theme};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ColorSupport {
+    #[default]
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+impl ColorSupport {
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm.contains("truecolor") || colorterm.contains("24bit") {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else if term.is_empty() {
+            ColorSupport::TrueColor
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    /// Converts an RGB color to the closest one the terminal can show.
+    pub fn rgb(&self, r: u8, g: u8, b: u8) -> Color {
+        match self {
+            ColorSupport::TrueColor => Color::Rgb(r, g, b),
+            ColorSupport::Ansi256 => Color::Indexed(to_ansi_256(r, g, b)),
+            ColorSupport::Ansi16 => to_ansi_16(r, g, b),
+        }
+    }
+
+    pub fn color(&self, color: SyntectColor) -> Color {
+        self.rgb(color.r, color.g, color.b)
+    }
+}
+
+fn to_ansi_256(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            _ => 232 + ((r - 8) / 10).min(23),
+        };
+    }
+    let level = |v: u8| -> u8 {
+        match v {
+            0..=47 => 0,
+            48..=114 => 1,
+            _ => (v - 35) / 40,
+        }
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+fn to_ansi_16(r: u8, g: u8, b: u8) -> Color {
+    let distance = |(cr, cg, cb): (u8, u8, u8)| -> i32 {
+        let (dr, dg, db) = (
+            r as i32 - cr as i32,
+            g as i32 - cg as i32,
+            b as i32 - cb as i32,
+        );
+        dr * dr + dg * dg + db * db
+    };
+    ANSI_16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::Reset)
+}
+
+/// Styles for the editor chrome, taken from the active color scheme's global settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UiTheme {
+    pub header: Style,
+    pub footer: Style,
+    pub gutter: Style,
+    pub cursor_line: Style,
+    pub selection: Style,
+    pub fold: Style,
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        Self {
+            header: Style::default().fg(Color::Black).bg(Color::White),
+            footer: Style::default().fg(Color::Black).bg(Color::White),
+            gutter: Style::default().fg(Color::Yellow),
+            cursor_line: Style::default().bg(Color::DarkGray),
+            selection: Style::default().bg(Color::Gray).fg(Color::Black),
+            fold: Style::default().bg(Color::DarkGray).fg(Color::Cyan),
+        }
+    }
+}
+
+impl UiTheme {
+    pub fn from_theme(theme: &Theme, support: ColorSupport) -> Self {
+        let settings = &theme.settings;
+        let defaults = Self::default();
+        let color = |c: Option<SyntectColor>| c.map(|c| support.color(c));
+        let style = |fg: Option<Color>, bg: Option<Color>, fallback: Style| match (fg, bg) {
+            (None, None) => fallback,
+            (fg, bg) => {
+                let style = fg.map(|fg| fallback.fg(fg)).unwrap_or(fallback);
+                bg.map(|bg| style.bg(bg)).unwrap_or(style)
+            }
+        };
+        let foreground = color(settings.foreground);
+        let background = color(settings.background);
+        Self {
+            header: style(background, foreground, defaults.header),
+            footer: style(background, foreground, defaults.footer),
+            gutter: style(
+                color(settings.gutter_foreground).or(foreground),
+                color(settings.gutter),
+                defaults.gutter,
+            ),
+            cursor_line: style(None, color(settings.line_highlight), defaults.cursor_line),
+            selection: style(
+                color(settings.selection_foreground),
+                color(settings.selection),
+                defaults.selection,
+            ),
+            fold: style(
+                color(settings.gutter_foreground).or(foreground),
+                color(settings.line_highlight),
+                defaults.fold,
+            ),
+        }
+    }
+}
+
+/// Directory user `.tmTheme` files are loaded from: `$XDG_CONFIG_HOME/ri/themes`, falling back
+/// to `~/.config/ri/themes`.
+pub fn theme_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("ri").join("themes"))
+}
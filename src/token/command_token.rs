@@ -1,7 +1,7 @@
 use crate::app::Mode;
 use anyhow::Error as AnyHowError;
 use crossterm::event::{KeyCode, KeyEvent as Key};
-use std::{convert::TryFrom, iter::Iterator};
+use std::{convert::TryFrom, iter, iter::Iterator};
 use uuid::Uuid;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -9,8 +9,30 @@ pub enum CommandToken {
     NoOp,
     SetMode(Mode),
     Quit,
+    ForceQuit,
+    QuitAll,
+    ForceQuitAll,
     Write,
-    TabNew,
+    WriteTo(String),
+    AppendTo(String),
+    SaveAs(String),
+    WriteQuit,
+    WriteQuitIfModified,
+    WriteAll,
+    WriteQuitAll,
+    Edit(Option<String>),
+    Reload,
+    Confirm(char),
+    Set(String),
+    Recover,
+    ForceWrite,
+    CheckTime,
+    ColorScheme(Option<String>),
+    Complete,
+    TabNew(Option<String>),
+    TabClose(bool),
+    TabOnly(bool),
+    TabMove(String),
     Append(String),
     Remove,
     VerticalSplit(Option<String>),
@@ -19,6 +41,15 @@ pub enum CommandToken {
     Esc,
     Enter,
     SetBuffer(Uuid),
+    ListBuffers,
+    Buffer(String),
+    NextBuffer,
+    PrevBuffer,
+    DeleteBuffer(Option<String>, bool),
+    WipeBuffer(Option<String>, bool),
+    Resize(String),
+    VerticalResize(String),
+    Only(bool),
     GoToLine(usize),
     YankLines(usize, usize),
     DeleteLines(usize, usize),
@@ -37,10 +68,125 @@ impl TryFrom<&[char]> for CommandToken {
 impl TryFrom<&Vec<char>> for CommandToken {
     type Error = AnyHowError;
     fn try_from(value: &Vec<char>) -> Result<Self, Self::Error> {
-        let command_token = match &value[..] {
-            [':', 'q', ..] => Ok(Self::Quit),
-            [':', 'w', ..] => Ok(Self::Write),
+        let trimmed = value
+            .iter()
+            .collect::<String>()
+            .trim_end()
+            .chars()
+            .collect::<Vec<char>>();
+        let command_token = match &trimmed[..] {
+            [':', 'q'] => Ok(Self::Quit),
+            [':', 'q', '!'] => Ok(Self::ForceQuit),
+            [':', 'q', 'a'] => Ok(Self::QuitAll),
+            [':', 'q', 'a', '!'] => Ok(Self::ForceQuitAll),
+            [':', 'w'] => Ok(Self::Write),
+            [':', 'w', '!'] => Ok(Self::ForceWrite),
+            [':', 'c', 'h', 'e', 'c', 'k', 't', 'i', 'm', 'e'] | [':', 'c', 'h', 'e'] => {
+                Ok(Self::CheckTime)
+            }
+            [':', 'c', 'o', 'l', 'o', 'r', 's', 'c', 'h', 'e', 'm', 'e']
+            | [':', 'c', 'o', 'l', 'o'] => Ok(Self::ColorScheme(None)),
+            [':', 'c', 'o', 'l', 'o', 'r', 's', 'c', 'h', 'e', 'm', 'e', ' ', rest @ ..]
+            | [':', 'c', 'o', 'l', 'o', ' ', rest @ ..] => Ok(Self::ColorScheme(Some(
+                rest.iter().collect::<String>().trim().to_owned(),
+            ))),
+            [':', 'w', 'q'] => Ok(Self::WriteQuit),
+            [':', 'x'] => Ok(Self::WriteQuitIfModified),
+            [':', 'w', 'a'] => Ok(Self::WriteAll),
+            [':', 'w', 'q', 'a'] | [':', 'x', 'a'] => Ok(Self::WriteQuitAll),
+            [':', 'w', ' ', '>', '>', rest @ ..] => Ok(Self::AppendTo(
+                rest.iter().collect::<String>().trim().to_owned(),
+            )),
+            [':', 'w', ' ', rest @ ..] => Ok(Self::WriteTo(
+                rest.iter().collect::<String>().trim().to_owned(),
+            )),
+            [':', 's', 'a', 'v', 'e', 'a', 's', ' ', rest @ ..] => Ok(Self::SaveAs(
+                rest.iter().collect::<String>().trim().to_owned(),
+            )),
+            [':', 's', 'e', 't', ' ', rest @ ..] | [':', 's', 'e', ' ', rest @ ..] => {
+                Ok(Self::Set(rest.iter().collect::<String>().trim().to_owned()))
+            }
+            [':', 'r', 'e', 'c', 'o', 'v', 'e', 'r'] | [':', 'r', 'e', 'c'] => Ok(Self::Recover),
+            [':', 'e', '!'] => Ok(Self::Reload),
+            [':', 'e'] => Ok(Self::Edit(None)),
+            [':', 'e', ' ', rest @ ..] => Ok(Self::Edit(Some(
+                rest.iter().collect::<String>().trim().to_owned(),
+            ))),
             ['\n', ..] => Ok(Self::Enter),
+            [':', 'l', 's'] | [':', 'f', 'i', 'l', 'e', 's'] => Ok(Self::ListBuffers),
+            [':', 'b', 'u', 'f', 'f', 'e', 'r', 's'] => Ok(Self::ListBuffers),
+            [':', 'b', 'n'] | [':', 'b', 'n', 'e', 'x', 't'] => Ok(Self::NextBuffer),
+            [':', 'b', 'p'] | [':', 'b', 'p', 'r', 'e', 'v', 'i', 'o', 'u', 's'] => {
+                Ok(Self::PrevBuffer)
+            }
+            [':', 'b', 'd', 'e', 'l', 'e', 't', 'e', rest @ ..] | [':', 'b', 'd', rest @ ..]
+                if is_buffer_arg(rest) =>
+            {
+                let (arg, force) = buffer_arg(rest);
+                Ok(Self::DeleteBuffer(arg, force))
+            }
+            [':', 'b', 'w', 'i', 'p', 'e', 'o', 'u', 't', rest @ ..]
+            | [':', 'b', 'w', rest @ ..]
+                if is_buffer_arg(rest) =>
+            {
+                let (arg, force) = buffer_arg(rest);
+                Ok(Self::WipeBuffer(arg, force))
+            }
+            [':', 'b', 'u', 'f', 'f', 'e', 'r', ' ', rest @ ..] | [':', 'b', ' ', rest @ ..] => Ok(
+                Self::Buffer(rest.iter().collect::<String>().trim().to_owned()),
+            ),
+            [':', 'b', rest @ ..]
+                if !rest.is_empty() && rest.iter().all(|c| c.is_ascii_digit()) =>
+            {
+                Ok(Self::Buffer(rest.iter().collect::<String>()))
+            }
+            [':', 'r', 'e', 's', 'i', 'z', 'e', rest @ ..] | [':', 'r', 'e', 's', rest @ ..]
+                if rest.first().map(|c| *c == ' ').unwrap_or(true) =>
+            {
+                Ok(Self::Resize(
+                    rest.iter().collect::<String>().trim().to_owned(),
+                ))
+            }
+            [':', 'v', 'e', 'r', 't', 'i', 'c', 'a', 'l', ' ', rest @ ..]
+            | [':', 'v', 'e', 'r', 't', ' ', rest @ ..] => {
+                let command = iter::once(':')
+                    .chain(rest.iter().cloned().skip_while(|c| *c == ' '))
+                    .collect::<Vec<char>>();
+                match Self::try_from(&command) {
+                    Ok(Self::Resize(arg)) => Ok(Self::VerticalResize(arg)),
+                    _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
+                }
+            }
+            [':', 't', 'a', 'b', 'n', 'e', 'w', rest @ ..]
+            | [':', 't', 'a', 'b', 'e', 'd', 'i', 't', rest @ ..]
+            | [':', 't', 'a', 'b', 'e', rest @ ..]
+                if rest.first().map(|c| *c == ' ').unwrap_or(true) =>
+            {
+                let file_name = rest.iter().collect::<String>().trim().to_owned();
+                Ok(Self::TabNew(Some(file_name).filter(|f| !f.is_empty())))
+            }
+            [':', 't', 'a', 'b', 'c', 'l', 'o', 's', 'e'] | [':', 't', 'a', 'b', 'c'] => {
+                Ok(Self::TabClose(false))
+            }
+            [':', 't', 'a', 'b', 'c', 'l', 'o', 's', 'e', '!'] | [':', 't', 'a', 'b', 'c', '!'] => {
+                Ok(Self::TabClose(true))
+            }
+            [':', 't', 'a', 'b', 'o', 'n', 'l', 'y'] | [':', 't', 'a', 'b', 'o'] => {
+                Ok(Self::TabOnly(false))
+            }
+            [':', 't', 'a', 'b', 'o', 'n', 'l', 'y', '!'] | [':', 't', 'a', 'b', 'o', '!'] => {
+                Ok(Self::TabOnly(true))
+            }
+            [':', 't', 'a', 'b', 'm', 'o', 'v', 'e', rest @ ..]
+            | [':', 't', 'a', 'b', 'm', rest @ ..]
+                if rest.first().map(|c| *c == ' ').unwrap_or(true) =>
+            {
+                Ok(Self::TabMove(
+                    rest.iter().collect::<String>().trim().to_owned(),
+                ))
+            }
+            [':', 'o', 'n', 'l', 'y'] | [':', 'o', 'n'] => Ok(Self::Only(false)),
+            [':', 'o', 'n', 'l', 'y', '!'] | [':', 'o', 'n', '!'] => Ok(Self::Only(true)),
             [':', 'v', 's', rest @ ..] => {
                 Ok(Self::VerticalSplit(Some(rest.iter().collect::<String>())))
             }
@@ -106,6 +252,32 @@ impl TryFrom<&Vec<char>> for CommandToken {
     }
 }
 
+/// Whether `rest` can follow `:bd`/`:bw`: nothing, a `!`, or a buffer number or name.
+fn is_buffer_arg(rest: &[char]) -> bool {
+    match rest {
+        [] | ['!', ..] | [' ', ..] => true,
+        _ => rest.iter().all(|c| c.is_ascii_digit()),
+    }
+}
+
+fn buffer_arg(rest: &[char]) -> (Option<String>, bool) {
+    let (rest, force) = match rest {
+        ['!', rest @ ..] => (rest, true),
+        _ => (rest, false),
+    };
+    let arg = rest.iter().collect::<String>().trim().to_owned();
+    (Some(arg).filter(|a| !a.is_empty()), force)
+}
+
+impl CommandToken {
+    pub fn confirm_from_key(key: &Key) -> Self {
+        match key.code {
+            KeyCode::Char(c) => Self::Confirm(c.to_ascii_lowercase()),
+            _ => Self::Confirm('\u{1b}'),
+        }
+    }
+}
+
 impl TryFrom<&Key> for CommandToken {
     type Error = AnyHowError;
 
@@ -114,6 +286,7 @@ impl TryFrom<&Key> for CommandToken {
             KeyCode::Enter => Ok(Self::Enter),
             KeyCode::Esc => Ok(Self::Esc),
             KeyCode::Backspace => Ok(Self::Remove),
+            KeyCode::Tab => Ok(Self::Complete),
             _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
         }
     }
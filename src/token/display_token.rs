@@ -8,11 +8,11 @@ use uuid::Uuid;
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct WindowChange {
     pub id: Uuid,
-    pub x_pos: u16,
-    pub y_pos: u16,
+    pub x_pos: usize,
+    pub y_pos: usize,
     pub title: Option<String>,
-    pub page_size: u16,
-    pub current_page: u16,
+    pub page_size: usize,
+    pub current_page: usize,
     pub area: Option<Rect>,
 }
 
@@ -5,6 +5,7 @@ pub mod insert_token;
 pub mod motion_token;
 pub mod normal_token;
 pub mod operator_token;
+pub mod visual_token;
 use anyhow::{Error as AnyHowError, Result as AnyHowResult};
 use crossterm::event::KeyEvent as Key;
 use std::convert::TryFrom;
@@ -17,6 +18,7 @@ pub use insert_token::InsertToken;
 pub use motion_token::MotionToken;
 pub use normal_token::NormalToken;
 pub use operator_token::OperatorToken;
+pub use visual_token::VisualToken;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Token {
@@ -26,6 +28,7 @@ pub enum Token {
     Insert(InsertToken),
     Normal(NormalToken),
     Operator(OperatorToken),
+    Visual(VisualToken),
     Display(DisplayToken),
 }
 
@@ -39,7 +42,18 @@ pub fn get_tokens_from_chars(mode: &Mode, input: &Vec<char>) -> Vec<Token> {
         let unmatched = &input[skip_to..=idx];
         let token_result = match mode {
             Mode::Normal => {
-                if let Ok(normal) = NormalToken::try_from(unmatched) {
+                let after_operator = matches!(tokens.last(), Some(Token::Operator(_)));
+                let motion_first = match unmatched.first() {
+                    Some(']') | Some('[') => true,
+                    Some('i') | Some('a') => after_operator,
+                    _ => false,
+                };
+                if motion_first && is_motion_prefix(unmatched) {
+                    None
+                } else if let (true, Ok(motion)) = (motion_first, MotionToken::try_from(unmatched))
+                {
+                    Some(Token::Motion(motion))
+                } else if let Ok(normal) = NormalToken::try_from(unmatched) {
                     Some(Token::Normal(normal))
                 } else if let Ok(operator) = OperatorToken::try_from(unmatched) {
                     Some(Token::Operator(operator))
@@ -70,6 +84,19 @@ pub fn get_tokens_from_chars(mode: &Mode, input: &Vec<char>) -> Vec<Token> {
                     None
                 }
             }
+            Mode::Visual => {
+                if is_motion_prefix(unmatched) {
+                    None
+                } else if let Ok(motion) = MotionToken::try_from(unmatched) {
+                    Some(Token::Motion(motion))
+                } else if let Ok(visual) = VisualToken::try_from(unmatched) {
+                    Some(Token::Visual(visual))
+                } else if let Ok(operator) = OperatorToken::try_from(unmatched) {
+                    Some(Token::Operator(operator))
+                } else {
+                    None
+                }
+            }
             _ => None,
         };
         if let Some(token) = token_result {
@@ -80,6 +107,12 @@ pub fn get_tokens_from_chars(mode: &Mode, input: &Vec<char>) -> Vec<Token> {
     tokens
 }
 
+/// First halves of two-char text objects and bracket motions, which have to wait for the
+/// next char instead of matching a shorter token.
+fn is_motion_prefix(chars: &[char]) -> bool {
+    matches!(chars, ['i'] | ['a'] | [']'] | ['['])
+}
+
 pub fn get_token_from_chars(mode: &Mode, input: &Vec<char>) -> AnyHowResult<Token> {
     match mode {
         Mode::Command => Ok(Token::Command(CommandToken::try_from(input)?)),
@@ -103,6 +136,13 @@ pub fn get_token_from_key(mode: &Mode, event: &Key) -> AnyHowResult<Token> {
         Mode::Command => Ok(Token::Command(CommandToken::try_from(event)?)),
         Mode::Insert => Ok(Token::Insert(InsertToken::try_from(event)?)),
         Mode::Append => Ok(Token::Append(AppendToken::try_from(event)?)),
-        _ => Err(AnyHowError::msg("No Tokens Found".to_string())),
+        Mode::Confirm => Ok(Token::Command(CommandToken::confirm_from_key(event))),
+        Mode::Visual => {
+            if let Ok(normal) = NormalToken::try_from(event) {
+                Ok(Token::Normal(normal))
+            } else {
+                Ok(Token::Motion(MotionToken::try_from(event)?))
+            }
+        }
     }
 }
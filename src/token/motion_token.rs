@@ -1,5 +1,5 @@
 use anyhow::Error as AnyHowError;
-use crossterm::event::{KeyCode, KeyEvent as Key};
+use crossterm::event::{KeyCode, KeyEvent as Key, KeyModifiers};
 use std::{convert::TryFrom, iter::Iterator};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -22,6 +22,20 @@ pub enum MotionToken {
     EndWord,
     BackWord,
     InnerWord,
+    InnerFunction,
+    AroundFunction,
+    InnerClass,
+    AroundClass,
+    InnerArgument,
+    AroundArgument,
+    NextFunction,
+    PrevFunction,
+    ExpandSelection,
+    NextFold,
+    PrevFold,
+    WindowTop,
+    WindowMiddle,
+    WindowBottom,
 }
 
 impl TryFrom<&[char]> for MotionToken {
@@ -29,6 +43,16 @@ impl TryFrom<&[char]> for MotionToken {
     fn try_from(value: &[char]) -> Result<Self, Self::Error> {
         match value {
             ['i', 'w', ..] => Ok(Self::InnerWord),
+            ['i', 'f', ..] => Ok(Self::InnerFunction),
+            ['a', 'f', ..] => Ok(Self::AroundFunction),
+            ['i', 'c', ..] => Ok(Self::InnerClass),
+            ['a', 'c', ..] => Ok(Self::AroundClass),
+            ['i', 'a', ..] => Ok(Self::InnerArgument),
+            ['a', 'a', ..] => Ok(Self::AroundArgument),
+            [']', 'f', ..] => Ok(Self::NextFunction),
+            ['[', 'f', ..] => Ok(Self::PrevFunction),
+            ['z', 'j', ..] => Ok(Self::NextFold),
+            ['z', 'k', ..] => Ok(Self::PrevFold),
             ['0', ..] => Ok(Self::First),
             ['^', ..] => Ok(Self::FirstNonBlank),
             ['$', ..] => Ok(Self::Last),
@@ -39,6 +63,9 @@ impl TryFrom<&[char]> for MotionToken {
             ['g', '_', ..] => Ok(Self::LastNonBlank),
             ['g', 'g', ..] => Ok(Self::FirstLine),
             ['G', ..] => Ok(Self::LastLine),
+            ['H', ..] => Ok(Self::WindowTop),
+            ['M', ..] => Ok(Self::WindowMiddle),
+            ['L', ..] => Ok(Self::WindowBottom),
             ['w', ..] => Ok(Self::StartWord),
             ['e', ..] => Ok(Self::EndWord),
             ['b', ..] => Ok(Self::BackWord),
@@ -70,6 +97,9 @@ impl TryFrom<&Key> for MotionToken {
             KeyCode::Left => Ok(Self::Left),
             KeyCode::Right => Ok(Self::Right),
             KeyCode::Backspace => Ok(Self::Left),
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::ALT) => {
+                Ok(Self::ExpandSelection)
+            }
             _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
         }
     }
@@ -1,5 +1,9 @@
+use crate::app::HistoryKind;
+use crate::buffer::Scroll;
+use crate::layout::Edge;
 use anyhow::Error as AnyHowError;
-use crossterm::event::{KeyCode, KeyEvent as Key};
+use crossterm::event::{KeyCode, KeyEvent as Key, KeyModifiers};
+use ratatui::layout::Direction;
 use std::{convert::TryFrom, iter::Iterator};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -8,6 +12,18 @@ pub enum NormalToken {
     WindowRight,
     WindowUp,
     WindowDown,
+    NextWindow,
+    PrevWindow,
+    LastAccessedWindow,
+    TopWindow,
+    BottomWindow,
+    ExchangeWindow,
+    RotateWindows(bool),
+    MoveWindow(Edge),
+    ResizeWindow(Direction, i32),
+    MaximizeWindow(Direction),
+    EqualizeWindows,
+    OnlyWindow,
     SwitchToInsert,
     SwitchToAppend,
     AddNewLineBelow,
@@ -22,15 +38,69 @@ pub enum NormalToken {
     Esc,
     Enter,
     SwitchToCommand,
+    SwitchToSearch,
     SetWindow(usize),
+    CommandLineWindow(HistoryKind),
+    FoldOpen,
+    FoldClose,
+    FoldToggle,
+    FoldOpenAll,
+    FoldCloseAll,
+    Scroll(Scroll),
+    AlternateBuffer,
+    NextTab,
+    PrevTab,
+    GoToTab(usize),
+    Reselect,
+    VisualBlock,
 }
 
 impl TryFrom<&[char]> for NormalToken {
     type Error = AnyHowError;
     fn try_from(value: &[char]) -> Result<Self, Self::Error> {
         match value {
+            [WINDOW_PREFIX, c, ..] => match c {
+                'h' => Ok(Self::WindowLeft),
+                'j' => Ok(Self::WindowDown),
+                'k' => Ok(Self::WindowUp),
+                'l' => Ok(Self::WindowRight),
+                'w' => Ok(Self::NextWindow),
+                'W' => Ok(Self::PrevWindow),
+                'p' => Ok(Self::LastAccessedWindow),
+                't' => Ok(Self::TopWindow),
+                'b' => Ok(Self::BottomWindow),
+                'x' => Ok(Self::ExchangeWindow),
+                'r' => Ok(Self::RotateWindows(true)),
+                'R' => Ok(Self::RotateWindows(false)),
+                'H' => Ok(Self::MoveWindow(Edge::Left)),
+                'J' => Ok(Self::MoveWindow(Edge::Bottom)),
+                'K' => Ok(Self::MoveWindow(Edge::Top)),
+                'L' => Ok(Self::MoveWindow(Edge::Right)),
+                '+' => Ok(Self::ResizeWindow(Direction::Vertical, 1)),
+                '-' => Ok(Self::ResizeWindow(Direction::Vertical, -1)),
+                '>' => Ok(Self::ResizeWindow(Direction::Horizontal, 1)),
+                '<' => Ok(Self::ResizeWindow(Direction::Horizontal, -1)),
+                '_' => Ok(Self::MaximizeWindow(Direction::Vertical)),
+                '|' => Ok(Self::MaximizeWindow(Direction::Horizontal)),
+                '=' => Ok(Self::EqualizeWindows),
+                'o' => Ok(Self::OnlyWindow),
+                _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
+            },
+            ['g', 't', ..] => Ok(Self::NextTab),
+            ['g', 'T', ..] => Ok(Self::PrevTab),
+            ['g', 'v', ..] => Ok(Self::Reselect),
+            [count @ .., 'g', 't'] if is_count(count) => Ok(Self::GoToTab(
+                count
+                    .iter()
+                    .collect::<String>()
+                    .parse::<usize>()
+                    .unwrap_or_default(),
+            )),
+            ['q', ':', ..] => Ok(Self::CommandLineWindow(HistoryKind::Command)),
+            ['q', '/', ..] => Ok(Self::CommandLineWindow(HistoryKind::Search)),
             ['a', ..] => Ok(Self::SwitchToAppend),
             [':', ..] => Ok(Self::SwitchToCommand),
+            ['/', ..] => Ok(Self::SwitchToSearch),
             ['y', 'y', ..] => Ok(Self::YankLine),
             ['d', 'd', ..] => Ok(Self::DeleteLine),
             ['u', ..] => Ok(Self::Undo),
@@ -42,6 +112,14 @@ impl TryFrom<&[char]> for NormalToken {
             ['v', ..] => Ok(Self::Visual),
             ['V', ..] => Ok(Self::VisualLine),
             ['\n', ..] => Ok(Self::Enter),
+            ['z', 'o', ..] => Ok(Self::FoldOpen),
+            ['z', 'c', ..] => Ok(Self::FoldClose),
+            ['z', 'a', ..] => Ok(Self::FoldToggle),
+            ['z', 'R', ..] => Ok(Self::FoldOpenAll),
+            ['z', 'M', ..] => Ok(Self::FoldCloseAll),
+            ['z', 't', ..] => Ok(Self::Scroll(Scroll::CursorTop)),
+            ['z', 'z', ..] => Ok(Self::Scroll(Scroll::CursorCenter)),
+            ['z', 'b', ..] => Ok(Self::Scroll(Scroll::CursorBottom)),
             ['z', rest @ ..]
                 if rest
                     .iter()
@@ -63,7 +141,16 @@ impl TryFrom<&[char]> for NormalToken {
     }
 }
 
+/// Whether `chars` is a count typed before a command: digits not starting with `0`, which is
+/// a motion of its own.
+fn is_count(chars: &[char]) -> bool {
+    chars.first().map(|c| *c != '0').unwrap_or(false) && chars.iter().all(|c| c.is_ascii_digit())
+}
+
 pub const PARSE_FAILURE_ERR: &'static str = "Unknown Token";
+/// Stands in for `Ctrl-w` in the pending characters, so the key after it selects the window
+/// command.
+pub const WINDOW_PREFIX: char = '\u{17}';
 impl TryFrom<&Vec<char>> for NormalToken {
     type Error = AnyHowError;
 
@@ -77,7 +164,23 @@ impl TryFrom<&Key> for NormalToken {
 
     fn try_from(key: &Key) -> Result<Self, Self::Error> {
         match key.code {
+            KeyCode::PageDown if key.modifiers.contains(KeyModifiers::CONTROL) => Ok(Self::NextTab),
+            KeyCode::PageUp if key.modifiers.contains(KeyModifiers::CONTROL) => Ok(Self::PrevTab),
             KeyCode::Esc => Ok(Self::Esc),
+            KeyCode::Enter => Ok(Self::Enter),
+            KeyCode::PageDown => Ok(Self::Scroll(Scroll::PageDown)),
+            KeyCode::PageUp => Ok(Self::Scroll(Scroll::PageUp)),
+            KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => match c {
+                'e' => Ok(Self::Scroll(Scroll::LineDown)),
+                'y' => Ok(Self::Scroll(Scroll::LineUp)),
+                'd' => Ok(Self::Scroll(Scroll::HalfPageDown)),
+                'u' => Ok(Self::Scroll(Scroll::HalfPageUp)),
+                'f' => Ok(Self::Scroll(Scroll::PageDown)),
+                'b' => Ok(Self::Scroll(Scroll::PageUp)),
+                '^' | '6' => Ok(Self::AlternateBuffer),
+                'v' => Ok(Self::VisualBlock),
+                _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
+            },
             _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
         }
     }
@@ -14,6 +14,7 @@ pub enum OperatorToken {
     ToggleCase,
     Shell,
     Format,
+    Fold,
     Esc,
     Remove,
 }
@@ -33,6 +34,7 @@ impl TryFrom<&[char]> for OperatorToken {
             ['~', ..] => Ok(Self::ToggleCase),
             ['!', ..] => Ok(Self::Shell),
             ['=', ..] => Ok(Self::Format),
+            ['z', 'f', ..] => Ok(Self::Fold),
             _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
         }
     }
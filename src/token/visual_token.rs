@@ -0,0 +1,48 @@
+use anyhow::Error as AnyHowError;
+use std::convert::TryFrom;
+
+/// Keys that mean something of their own in visual mode. Operators (`d`, `y`, `c`, `>`, `<`,
+/// `~`, `zf`) are `OperatorToken`s there too and apply to the selection.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum VisualToken {
+    SwapEnds,
+    Join,
+    Command,
+    Lowercase,
+    Uppercase,
+    Char,
+    Line,
+    Insert,
+    Append,
+    Replace(char),
+}
+
+pub const PARSE_FAILURE_ERR: &str = "Unknown Token";
+
+impl TryFrom<&[char]> for VisualToken {
+    type Error = AnyHowError;
+
+    fn try_from(value: &[char]) -> Result<Self, Self::Error> {
+        match value {
+            ['o', ..] => Ok(Self::SwapEnds),
+            ['J', ..] => Ok(Self::Join),
+            [':', ..] => Ok(Self::Command),
+            ['u', ..] => Ok(Self::Lowercase),
+            ['U', ..] => Ok(Self::Uppercase),
+            ['v', ..] => Ok(Self::Char),
+            ['V', ..] => Ok(Self::Line),
+            ['I', ..] => Ok(Self::Insert),
+            ['A', ..] => Ok(Self::Append),
+            ['r', c, ..] => Ok(Self::Replace(*c)),
+            _ => Err(Self::Error::msg(PARSE_FAILURE_ERR)),
+        }
+    }
+}
+
+impl TryFrom<&Vec<char>> for VisualToken {
+    type Error = AnyHowError;
+
+    fn try_from(value: &Vec<char>) -> Result<Self, Self::Error> {
+        Self::try_from(&value[..])
+    }
+}
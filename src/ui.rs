@@ -1,4 +1,4 @@
-use crate::Mode;
+use crate::theme::UiTheme;
 use crate::Window;
 use std::io::Stdout;
 use std::rc::Rc;
@@ -6,7 +6,8 @@ use std::rc::Rc;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    style::Modifier,
+    text::{Line, Span},
     widgets::{Block, Paragraph, Wrap},
     Frame, Terminal,
 };
@@ -18,29 +19,41 @@ pub type Term = Terminal<CrosstermBackend<Stdout>>;
 pub struct Ui {
     pub should_quit: bool,
     pub current_window_id: Uuid,
+    pub tab_area: Option<Rect>,
     pub text_area: Rect,
     pub foot_area: Rect,
+    pub theme: UiTheme,
 }
 
 impl Ui {
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_view_port(
         &mut self,
         current_window_id: &Uuid,
-        mode: &Mode,
-        coords: Option<(u16, u16)>,
+        mode: &str,
+        coords: Option<(usize, usize)>,
+        modified: bool,
+        file_info: Option<String>,
         command_text: &Option<String>,
+        tab_labels: Vec<(String, bool)>,
         window_widgets: Vec<&Window>,
         terminal: &mut Term,
     ) {
+        let tab_area = self.tab_area;
         let foot_area = self.foot_area.clone();
         let text_area = self.text_area.clone();
         let _current_window_id = self.current_window_id.clone();
+        let theme = self.theme.clone();
         let _ = terminal.draw(|f| {
             Self::draw(
                 current_window_id,
+                &theme,
                 mode,
                 coords,
+                modified,
+                file_info,
                 command_text,
+                tab_area.map(|area| (area, tab_labels)),
                 foot_area,
                 text_area,
                 window_widgets,
@@ -61,16 +74,24 @@ impl Ui {
             .split(text_area)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         current_window_id: &Uuid,
-        mode: &Mode,
-        coords: Option<(u16, u16)>,
+        theme: &UiTheme,
+        mode: &str,
+        coords: Option<(usize, usize)>,
+        modified: bool,
+        file_info: Option<String>,
         command_text: &Option<String>,
+        tab_line: Option<(Rect, Vec<(String, bool)>)>,
         foot_area: Rect,
         text_area: Rect,
         window_widgets: Vec<&Window>,
         f: &mut Frame,
     ) {
+        if let Some((area, labels)) = tab_line {
+            Self::draw_tab_line(theme, labels, f, area);
+        }
         for window in window_widgets {
             if window.id == *current_window_id {
                 f.set_cursor(window.cursor_x_pos(), window.cursor_y_pos());
@@ -79,34 +100,83 @@ impl Ui {
             f.render_widget(window, text_area);
         }
 
-        Self::draw_footer(mode, coords, command_text, f, foot_area);
+        Self::draw_footer(
+            theme,
+            mode,
+            coords,
+            modified,
+            file_info,
+            command_text,
+            f,
+            foot_area,
+        );
     }
 
-    fn create_layout(frame: &Frame) -> (Rect, Rect) {
+    fn create_layout(size: Rect) -> (Rect, Rect) {
         let area = Layout::default()
             .constraints([Constraint::Min(20), Constraint::Length(1)].as_ref())
-            .split(frame.size());
+            .split(size);
         (area[0], area[1])
     }
 
+    /// Recomputes the window and footer areas for a terminal of `size`. With `tab_line` the
+    /// top row of the window area goes to the tab line.
+    pub fn resize(&mut self, size: Rect, tab_line: bool) {
+        let (text_area, foot_area) = Ui::create_layout(size);
+        self.tab_area = None;
+        self.text_area = text_area;
+        self.foot_area = foot_area;
+        if tab_line && text_area.height > 1 {
+            self.tab_area = Some(Rect::new(text_area.x, text_area.y, text_area.width, 1));
+            self.text_area = Rect::new(
+                text_area.x,
+                text_area.y + 1,
+                text_area.width,
+                text_area.height - 1,
+            );
+        }
+    }
+
+    /// One label per tab, the current one highlighted, on a row filled with the footer style.
+    fn draw_tab_line(theme: &UiTheme, labels: Vec<(String, bool)>, f: &mut Frame, area: Rect) {
+        let spans = labels
+            .into_iter()
+            .map(|(label, current)| {
+                if current {
+                    Span::styled(label, theme.header.add_modifier(Modifier::REVERSED))
+                } else {
+                    Span::styled(label, theme.footer)
+                }
+            })
+            .collect::<Vec<Span>>();
+        let paragraph = Paragraph::new(Line::from(spans)).style(theme.footer);
+        f.render_widget(paragraph, area);
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn draw_footer(
-        mode: &Mode,
-        coords: Option<(u16, u16)>,
+        theme: &UiTheme,
+        mode: &str,
+        coords: Option<(usize, usize)>,
+        modified: bool,
+        file_info: Option<String>,
         command_text: &Option<String>,
         f: &mut Frame,
         area: Rect,
     ) {
-        let block = Block::default().style(Style::default().fg(Color::Black).bg(Color::White));
+        let block = Block::default().style(theme.footer);
         let paragraph = Paragraph::new(command_text.clone().unwrap_or_default())
             .block(block.clone())
             .alignment(Alignment::Left)
             .wrap(Wrap { trim: true });
-        let paragraph2 = Paragraph::new(format!("{:?}", mode))
+        let paragraph2 = Paragraph::new(mode.to_owned())
             .block(block.clone())
             .alignment(Alignment::Center)
             .wrap(Wrap { trim: true });
         let paragraph3 = Paragraph::new(format!(
-            "{},{}",
+            "{}{} {},{}",
+            if modified { "[+] " } else { "" },
+            file_info.unwrap_or_default(),
             coords.unwrap_or_default().0,
             coords.unwrap_or_default().1
         ))
@@ -119,7 +189,7 @@ impl Ui {
     }
 
     pub fn new(terminal: &mut Term) -> Self {
-        let (text_area, foot_area) = Ui::create_layout(&terminal.get_frame());
+        let (text_area, foot_area) = Ui::create_layout(terminal.get_frame().size());
         Self {
             should_quit: false,
             current_window_id: Uuid::new_v4(),
@@ -1,29 +1,31 @@
 use crate::{add_safe, sub_safe};
 use crate::{
+    block,
+    buffer::Buffer,
+    fold::{self, Folds},
+    highlight::{HighlightCache, Highlighting},
     reflow::{LineComposer, WordWrapper},
+    syntax_tree::SyntaxTree,
+    theme::UiTheme,
     token::display_token::*,
 };
 use ratatui::{
     buffer::Buffer as TuiBuffer,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    style::Style,
     text::{Line, Span, StyledGrapheme},
     widgets::Widget,
 };
 use ropey::Rope;
 use std::iter;
-use std::sync::Arc;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::Style as SyntectStyle;
-use syntect::util::LinesWithEndings;
-use syntect::{
-    highlighting::{Theme, ThemeSet},
-    parsing::{SyntaxReference, SyntaxSet},
-};
 use uuid::Uuid;
 
 use unicode_width::UnicodeWidthStr;
 
+fn to_screen(n: usize) -> u16 {
+    n.min(u16::MAX as usize) as u16
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct WindowPosition {
     pub y: u32,
@@ -37,7 +39,6 @@ pub struct CachedSpan {
 }
 
 impl CachedSpan {
-    #[allow(dead_code)]
     fn raw(content: &str) -> Self {
         CachedSpan {
             content: content.to_owned(),
@@ -58,23 +59,35 @@ impl<'a> From<&CachedSpan> for Span<'a> {
 #[derive(Default, Clone)]
 pub struct Window {
     pub id: Uuid,
+    pub buffer_id: Uuid,
     pub order: usize,
     pub title: Option<String>,
     pub current_percent_size: u16,
     pub y_offset: u16,
     pub x_offset: u16,
-    pub x_pos: u16,
-    pub y_pos: u16,
-    pub page_size: u16,
-    pub current_page: u16,
+    pub x_pos: usize,
+    pub y_pos: usize,
+    pub page_size: usize,
+    pub current_page: usize,
+    pub start_select_pos: Option<usize>,
+    pub end_select_pos: Option<usize>,
+    pub large_file: bool,
+    pub cache_start: usize,
+    pub modified: bool,
     pub area: Option<Rect>,
     pub command_text: Option<String>,
     pub highlight_cache: Vec<Vec<CachedSpan>>,
     pub line_num_cache: Vec<Vec<CachedSpan>>,
-    pub syntax_set: Option<SyntaxSet>,
-    pub theme_set: Option<Arc<ThemeSet>>,
-    pub theme: Option<Theme>,
-    pub syntax: Option<SyntaxReference>,
+    pub highlighter: Option<HighlightCache>,
+    pub ui_theme: UiTheme,
+    pub cursor_line: bool,
+    pub tabstop: usize,
+    pub fold_column: bool,
+    pub folds: Folds,
+    pub fold_summaries: Vec<(usize, usize, String)>,
+    pub highlight_tick: usize,
+    pub edits_tracked: bool,
+    pub selection: Vec<(usize, u16, u16)>,
 }
 
 impl Widget for &Window {
@@ -87,7 +100,12 @@ impl Widget for &Window {
                 .split(area);
             let header_area = main_area[0];
             self.render_header(
-                format!("{} {}", self.order, self.title.clone().unwrap_or_default()),
+                format!(
+                    "{} {}{}",
+                    self.order,
+                    self.title.clone().unwrap_or_default(),
+                    if self.modified { " [+]" } else { "" }
+                ),
                 &header_area,
                 buf,
             );
@@ -97,40 +115,39 @@ impl Widget for &Window {
                 .split(main_area[1]);
             let line_number_area = inner_text_splits[0];
             let text_area = inner_text_splits[1];
-            let spans = self
-                .highlight_cache
+            let rows = self.visible_rows(text_area.height as usize);
+            let spans = rows
                 .iter()
-                .map(|outer| {
-                    Line::from(
-                        outer
-                            .iter()
-                            .map(|inner| inner.into())
-                            .collect::<Vec<Span>>(),
-                    )
+                .map(|(line, summary)| match summary {
+                    Some(summary) => Line::from(Span::styled(summary.clone(), self.ui_theme.fold)),
+                    None => Window::cached_line(
+                        &self.highlight_cache,
+                        sub_safe(*line, self.cache_start),
+                    ),
                 })
                 .collect::<Vec<Line>>();
-
-            let line_number_spans = self
-                .line_num_cache
+            let line_number_spans = rows
                 .iter()
-                .map(|outer| {
-                    Line::from(
-                        outer
-                            .iter()
-                            .map(|inner| inner.into())
-                            .collect::<Vec<Span>>(),
-                    )
+                .map(|(line, _)| {
+                    Window::cached_line(&self.line_num_cache, sub_safe(*line, self.cache_start))
                 })
                 .collect::<Vec<Line>>();
             self.render_text(line_number_area, line_number_spans, buf);
+            if self.fold_column {
+                self.render_fold_column(line_number_area, &rows, buf);
+            }
             self.render_text(text_area, spans, buf);
+            if self.cursor_line {
+                self.render_cursor_line(text_area, buf);
+            }
+            self.render_selection(text_area, &rows, buf);
         }
     }
 }
 
 impl Window {
     fn render_header(&self, title: String, area: &Rect, buf: &mut TuiBuffer) {
-        let style = Style::default().fg(Color::Black).bg(Color::White);
+        let style = self.ui_theme.header;
         let mut title_chars = title[..].chars();
         for x in area.left()..area.right() {
             if let Some(current_char) = title_chars.next() {
@@ -142,6 +159,144 @@ impl Window {
             }
         }
     }
+    fn cached_line<'a>(cache: &[Vec<CachedSpan>], idx: usize) -> Line<'a> {
+        Line::from(
+            cache
+                .get(idx)
+                .map(|spans| spans.iter().map(|span| span.into()).collect::<Vec<Span>>())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// The buffer lines shown from the top of the viewport, with the summary to draw instead
+    /// where a line starts a closed fold.
+    fn visible_rows(&self, height: usize) -> Vec<(usize, Option<String>)> {
+        let line_count = add_safe(self.cache_start, self.highlight_cache.len());
+        let mut rows = vec![];
+        let mut line = self.current_page;
+        while line < line_count && rows.len() < height {
+            match self
+                .fold_summaries
+                .iter()
+                .find(|(start, end, _)| *start <= line && line <= *end)
+            {
+                Some((start, end, summary)) => {
+                    rows.push((*start, Some(summary.clone())));
+                    line = end + 1;
+                }
+                None => {
+                    rows.push((line, None));
+                    line += 1;
+                }
+            }
+        }
+        rows
+    }
+
+    fn render_fold_column(
+        &self,
+        area: Rect,
+        rows: &[(usize, Option<String>)],
+        buf: &mut TuiBuffer,
+    ) {
+        for (row, (line, _)) in rows.iter().enumerate() {
+            let y = add_safe(area.top(), to_screen(row));
+            if y >= sub_safe(area.bottom(), 2) {
+                break;
+            }
+            buf.get_mut(area.left(), y)
+                .set_symbol(&self.folds.column_marker(*line).to_string())
+                .set_style(self.ui_theme.gutter);
+        }
+    }
+
+    /// Screen rows between the top of the viewport and `line`, counting each closed fold as
+    /// one row.
+    fn screen_row(&self, line: usize) -> usize {
+        let hidden = self
+            .fold_summaries
+            .iter()
+            .filter(|(start, _, _)| *start >= self.current_page && *start < line)
+            .map(|(start, end, _)| end.min(&sub_safe(line, 1)) - start)
+            .sum::<usize>();
+        sub_safe(sub_safe(line, self.current_page), hidden)
+    }
+
+    /// Copies the buffer's folds and renders the summary line of each closed one.
+    pub fn sync_folds(&mut self, folds: &Folds, text: &Rope, fold_column: bool) {
+        self.fold_column = fold_column;
+        self.fold_summaries = folds
+            .closed_ranges()
+            .into_iter()
+            .map(|(start, end)| {
+                let summary = fold::summary(text, start, end, folds.level(start));
+                (start, end, summary)
+            })
+            .collect();
+        self.folds = folds.clone();
+    }
+
+    /// Works out the cells of each visible line that the chars `ranges` cover, so the
+    /// selection can be drawn over the highlighted text. A selected line break shows as one
+    /// cell past the end of its line.
+    pub fn sync_selection(&mut self, text: &Rope, ranges: &[(usize, usize)]) {
+        self.selection.clear();
+        for (start, end) in ranges {
+            let (start, end) = (*start, (*end).min(text.len_chars()));
+            if start >= end {
+                continue;
+            }
+            let first = text.char_to_line(start).max(self.current_page);
+            let last = text
+                .char_to_line(sub_safe(end, 1))
+                .min(sub_safe(self.viewport_end(), 1));
+            for line in first..=last {
+                let line_start = text.line_to_char(line);
+                let content = text.line(line).chars().collect::<String>();
+                let content = content.trim_end_matches(&['\r', '\n'][..]);
+                let columns = block::columns(content, self.tabstop);
+                let width = |chars: usize| to_screen(columns[chars.min(columns.len() - 1)]);
+                let from = width(sub_safe(start, line_start));
+                let selected = sub_safe(end, line_start);
+                let to = if selected >= columns.len() {
+                    add_safe(width(selected), 1)
+                } else {
+                    width(selected)
+                };
+                self.selection.push((line, from, to.max(add_safe(from, 1))));
+            }
+        }
+    }
+
+    fn render_selection(
+        &self,
+        text_area: Rect,
+        rows: &[(usize, Option<String>)],
+        buf: &mut TuiBuffer,
+    ) {
+        for (row, (line, _)) in rows.iter().enumerate() {
+            let y = add_safe(text_area.top(), to_screen(row));
+            if y >= sub_safe(text_area.bottom(), 2) {
+                break;
+            }
+            if let Some((_, from, to)) = self.selection.iter().find(|(l, _, _)| l == line) {
+                let x = add_safe(add_safe(text_area.left(), *from), 1);
+                let width = sub_safe(*to, *from).min(sub_safe(text_area.right(), x));
+                buf.set_style(Rect::new(x, y, width, 1), self.ui_theme.selection);
+            }
+        }
+    }
+
+    fn render_cursor_line(&self, text_area: Rect, buf: &mut TuiBuffer) {
+        let row = add_safe(text_area.top(), to_screen(self.screen_row(self.y_pos)));
+        if row < sub_safe(text_area.bottom(), 2) {
+            buf.set_style(
+                Rect::new(text_area.x, row, text_area.width, 1),
+                self.ui_theme.cursor_line,
+            );
+        }
+    }
+
     /*
     fn render_border(&self, area: &Rect, buf: &mut TuiBuffer) {
         let style = Style::default();
@@ -197,110 +352,174 @@ impl Window {
     */
 
     pub fn cache_window_content(&mut self, text: &Rope) {
+        if self.large_file {
+            return self.cache_viewport(text);
+        }
         self.cache_formatted_text(&text);
         self.cache_line_numbers(&text);
     }
 
-    pub fn set_highlight(&mut self) {
-        let ps = SyntaxSet::load_defaults_newlines();
-        let ts = ThemeSet::load_defaults();
-        let syntax = ps.find_syntax_by_extension("rs").clone();
-        let theme = ts.themes["base16-ocean.dark"].clone();
-        self.syntax_set = Some(ps.clone());
-        self.theme_set = Some(Arc::new(ts));
-        if let Some(s) = syntax {
-            self.syntax = Some(s.clone());
-        }
-        self.theme = Some(theme);
+    /// Large files skip highlighting and only keep the visible lines cached, so memory stays
+    /// proportional to the window rather than the file.
+    pub fn cache_viewport(&mut self, text: &Rope) {
+        let height = self
+            .area
+            .map(|a| a.height as usize)
+            .unwrap_or(self.page_size);
+        let start = self.current_page.min(text.len_lines());
+        let end = (start + height).min(text.len_lines());
+        self.highlight_cache = (start..end)
+            .map(|idx| {
+                vec![CachedSpan::raw(
+                    &text
+                        .line(idx)
+                        .chars()
+                        .filter(|c| *c != '\n')
+                        .collect::<String>(),
+                )]
+            })
+            .collect::<Vec<Vec<CachedSpan>>>();
+        self.line_num_cache = (start + 1..=end)
+            .map(|l| {
+                vec![CachedSpan {
+                    content: format!("{l:<4}"),
+                    style: self.ui_theme.gutter,
+                }]
+            })
+            .collect::<Vec<Vec<CachedSpan>>>();
+        self.cache_start = start;
     }
 
-    fn convert_style(style: SyntectStyle) -> Style {
-        Style::default().fg(Color::Rgb(
-            style.foreground.r,
-            style.foreground.g,
-            style.foreground.b,
-        ))
+    pub fn set_highlight(&mut self, highlighting: &Highlighting, buffer: &Buffer) {
+        self.highlighter =
+            Some(highlighting.cache_for(&buffer.filetype, buffer.syntax_tree.is_some()));
+        self.ui_theme = highlighting.ui_theme.clone();
     }
 
-    fn to_cached_span(style: SyntectStyle, value: &str) -> CachedSpan {
-        CachedSpan {
-            content: value.to_owned(),
-            style: Self::convert_style(style),
-        }
+    /// Rows of text the window shows below its header; render_text keeps the last two rows of
+    /// the area clear.
+    pub fn text_height(&self) -> usize {
+        self.area
+            .map(|a| sub_safe(a.height, 3).max(1) as usize)
+            .unwrap_or(self.page_size)
     }
 
-    fn to_spans(highlights: Vec<(SyntectStyle, &str)>) -> Vec<CachedSpan> {
-        highlights
+    /// First line below the viewport, past any lines hidden in closed folds.
+    fn viewport_end(&self) -> usize {
+        let height = self
+            .area
+            .map(|a| a.height as usize)
+            .unwrap_or(self.page_size);
+        let hidden = self
+            .fold_summaries
             .iter()
-            .map(|h| Self::to_cached_span(h.0, h.1))
-            .collect::<Vec<CachedSpan>>()
+            .filter(|(_, end, _)| *end >= self.current_page)
+            .map(|(start, end, _)| end - start)
+            .sum::<usize>();
+        add_safe(add_safe(self.current_page, height), hidden)
+    }
+
+    /// Highlights whatever the viewport needs. Edits that did not go through the cache_*
+    /// functions (or left the line count out of step) are only known by the buffer's change
+    /// tick, so they restart highlighting from the top. Tree-sitter highlighting has no
+    /// line states to keep and simply redoes the visible lines from the current tree.
+    pub fn highlight_viewport(
+        &mut self,
+        text: &Rope,
+        changed_tick: usize,
+        syntax_tree: Option<&SyntaxTree>,
+    ) {
+        if self.large_file {
+            return;
+        }
+        let end = self.viewport_end();
+        if let Some(highlighter) = self.highlighter.as_mut() {
+            if let (true, Some(tree)) = (highlighter.tree_sitter, syntax_tree) {
+                highlighter.highlight_tree(
+                    text,
+                    tree,
+                    &mut self.highlight_cache,
+                    self.current_page,
+                    end,
+                );
+                self.highlight_tick = changed_tick;
+                return;
+            }
+            let untracked = changed_tick != self.highlight_tick && !self.edits_tracked;
+            if untracked || self.highlight_cache.len() != text.len_lines() {
+                highlighter.reset();
+            }
+            highlighter.highlight(text, &mut self.highlight_cache, end);
+        }
+        self.highlight_tick = changed_tick;
+        self.edits_tracked = false;
     }
 
     pub fn cache_formatted_text(&mut self, text: &Rope) {
-        if let (Some(syntax), Some(theme)) = (&self.syntax, &self.theme) {
-            let mut highlight = HighlightLines::new(syntax, theme);
-            let mut spans: Vec<Vec<CachedSpan>> = vec![];
-            let rope_str = text.to_string();
-            let text_lines = LinesWithEndings::from(Box::leak(Box::new(rope_str)));
-            for line in text_lines {
-                if let Ok(hs) = highlight.highlight_line(line, &self.syntax_set.clone().unwrap()) {
-                    spans.push(Self::to_spans(hs.clone()));
-                }
+        let end = self.viewport_end();
+        if let Some(highlighter) = self.highlighter.as_mut() {
+            highlighter.reset();
+            self.highlight_cache.clear();
+            if !highlighter.tree_sitter {
+                highlighter.highlight(text, &mut self.highlight_cache, end);
             }
-            self.highlight_cache = spans.clone();
         }
     }
 
     pub fn cache_line_numbers(&mut self, text: &Rope) {
+        if self.large_file {
+            return self.cache_viewport(text);
+        }
         let line_count = text.len_lines();
-        let local_line_nums = Self::line_numbers(line_count);
+        let local_line_nums = Self::line_numbers(line_count, self.ui_theme.gutter);
         self.line_num_cache = local_line_nums.clone();
     }
 
     pub fn cache_new_line(&mut self, text: &Rope, line_index: usize) {
-        if let (Some(syntax), Some(theme)) = (&self.syntax, &self.theme) {
-            let mut highlight = HighlightLines::new(syntax, theme);
-            let rope_str = text
-                .get_line(line_index)
-                .map(|l| l.to_string())
-                .unwrap_or_default();
-            let mut text_line = LinesWithEndings::from(Box::leak(Box::new(rope_str)));
-            if let Some(line) = &text_line.nth(0) {
-                if let Ok(hs) = highlight.highlight_line(line, &self.syntax_set.clone().unwrap()) {
-                    self.highlight_cache
-                        .insert(line_index, Self::to_spans(hs.clone()));
-                }
+        if self.large_file {
+            return self.cache_viewport(text);
+        }
+        if let Some(highlighter) = self.highlighter.as_mut() {
+            highlighter.insert_line(line_index);
+            if line_index <= self.highlight_cache.len() {
+                self.highlight_cache.insert(line_index, vec![]);
             }
+            self.edits_tracked = true;
         }
     }
 
     pub fn remove_cache_line(&mut self, line_index: usize) {
-        let _ = self.highlight_cache.remove(line_index);
+        if self.large_file {
+            return;
+        }
+        if let Some(highlighter) = self.highlighter.as_mut() {
+            highlighter.remove_line(line_index);
+            self.edits_tracked = true;
+        }
+        if line_index < self.highlight_cache.len() {
+            let _ = self.highlight_cache.remove(line_index);
+        }
     }
 
     pub fn cache_current_line(&mut self, text: &Rope, line_index: usize) {
-        if let (Some(syntax), Some(theme)) = (&self.syntax, &self.theme) {
-            let mut highlight = HighlightLines::new(syntax, theme);
-            let rope_str = text
-                .get_line(line_index)
-                .map(|l| l.to_string())
-                .unwrap_or_default();
-            let mut text_line = LinesWithEndings::from(Box::leak(Box::new(rope_str)));
-            if let Some(line) = &text_line.nth(0) {
-                if let Ok(hs) = highlight.highlight_line(line, &self.syntax_set.clone().unwrap()) {
-                    self.highlight_cache[line_index] = Self::to_spans(hs.clone())
-                }
-            }
+        if self.large_file {
+            return self.cache_viewport(text);
+        }
+        if let Some(highlighter) = self.highlighter.as_mut() {
+            highlighter.invalidate(line_index);
+            self.edits_tracked = true;
         }
     }
 
-    fn line_numbers(line_number_count: usize) -> Vec<Vec<CachedSpan>> {
-        vec![(1..line_number_count)
-            .map(|l| CachedSpan {
-                content: format!("{l:<5}"),
-                style: Style::default().fg(Color::Yellow),
+    fn line_numbers(line_number_count: usize, style: Style) -> Vec<Vec<CachedSpan>> {
+        (1..line_number_count)
+            .map(|l| {
+                vec![CachedSpan {
+                    content: format!("{l:<4}"),
+                    style,
+                }]
             })
-            .collect::<Vec<CachedSpan>>()]
+            .collect::<Vec<Vec<CachedSpan>>>()
     }
 
     fn get_line_offset(line_width: u16, text_area_width: u16, alignment: Alignment) -> u16 {
@@ -330,21 +549,30 @@ impl Window {
             Box::new(WordWrapper::new(&mut styled, text_area.width, false));
         let mut y = 0;
         while let Some((current_line, current_line_width)) = line_composer.next_line() {
-            if y >= self.current_page {
-                let mut x =
-                    Self::get_line_offset(current_line_width, text_area.width, Alignment::Left);
-                for StyledGrapheme { symbol, style } in current_line {
+            let mut x = Self::get_line_offset(current_line_width, text_area.width, Alignment::Left);
+            for StyledGrapheme { symbol, style } in current_line {
+                // A tab fills the cells up to the next tabstop, as block::columns counts it.
+                let (symbol, cells, width) = match *symbol {
+                    "\t" => {
+                        let width = block::char_width('\t', x as usize, self.tabstop) as u16;
+                        let room = sub_safe(text_area.right(), add_safe(text_area.left(), x));
+                        (" ", width.min(room), width)
+                    }
+                    "" => (" ", 1, 0),
+                    symbol => (symbol, 1, symbol.width() as u16),
+                };
+                for cell in 0..cells {
                     buf.get_mut(
-                        add_safe(add_safe(text_area.left(), x), 1),
-                        sub_safe(add_safe(text_area.top(), y), self.current_page),
+                        add_safe(add_safe(text_area.left(), add_safe(x, cell)), 1),
+                        add_safe(text_area.top(), to_screen(y)),
                     )
-                    .set_symbol(if symbol.is_empty() { " " } else { symbol })
+                    .set_symbol(symbol)
                     .set_style(*style);
-                    x += symbol.width() as u16;
                 }
+                x += width;
             }
             y += 1;
-            if y >= add_safe(sub_safe(text_area.height, 2), self.current_page) {
+            if y >= sub_safe(text_area.height, 2) as usize {
                 break;
             }
         }
@@ -353,7 +581,8 @@ impl Window {
     pub fn new(change: &WindowChange) -> Self {
         if let Some(area) = change.area {
             Self {
-                id: change.id,
+                id: Uuid::new_v4(),
+                buffer_id: change.id,
                 x_pos: change.x_pos,
                 y_pos: change.y_pos,
                 title: change.title.clone(),
@@ -366,7 +595,8 @@ impl Window {
             }
         } else {
             Self {
-                id: change.id,
+                id: Uuid::new_v4(),
+                buffer_id: change.id,
                 x_pos: change.x_pos,
                 y_pos: change.y_pos,
                 title: change.title.clone(),
@@ -378,6 +608,13 @@ impl Window {
             }
         }
     }
+
+    pub fn set_area(&mut self, area: Rect) {
+        self.area = Some(area);
+        self.x_offset = add_safe(area.x, 4);
+        self.y_offset = add_safe(area.y, 1);
+    }
+
     pub fn cursor_x_pos(&self) -> u16 {
         let area = self.area.unwrap_or_default();
         if self.display_x_pos() >= area.right() {
@@ -387,7 +624,7 @@ impl Window {
         }
     }
     pub fn display_x_pos(&self) -> u16 {
-        add_safe(self.x_pos, self.x_offset)
+        add_safe(to_screen(self.x_pos), self.x_offset)
     }
 
     pub fn cursor_y_pos(&self) -> u16 {
@@ -400,7 +637,7 @@ impl Window {
     }
 
     pub fn display_y_pos(&self) -> u16 {
-        sub_safe(add_safe(self.y_pos, self.y_offset), self.current_page)
+        add_safe(to_screen(self.screen_row(self.y_pos)), self.y_offset)
     }
 
     pub fn get_origin(&self) -> Option<(u16, u16)> {
@@ -410,6 +647,42 @@ impl Window {
         None
     }
 
+    /// Stores the buffer's cursor, scroll and selection as this window's view, before another
+    /// window takes over the buffer's cursor.
+    pub fn save_view(&mut self, buffer: &Buffer) {
+        self.x_pos = buffer.x_pos;
+        self.y_pos = buffer.y_pos;
+        self.current_page = buffer.current_page;
+        self.start_select_pos = buffer.start_select_pos;
+        self.end_select_pos = buffer.end_select_pos;
+    }
+
+    /// Hands this window's view to the buffer, which edits and moves from it while the window
+    /// is current.
+    pub fn load_view(&mut self, buffer: &mut Buffer) {
+        self.clamp_view(&buffer.text);
+        buffer.x_pos = self.x_pos;
+        buffer.y_pos = self.y_pos;
+        buffer.current_page = self.current_page;
+        buffer.start_select_pos = self.start_select_pos;
+        buffer.end_select_pos = self.end_select_pos;
+    }
+
+    /// Keeps the view inside the text after edits made from another window, and the cursor
+    /// inside the window after it is resized.
+    pub fn clamp_view(&mut self, text: &Rope) {
+        let last_line = sub_safe(text.len_lines(), 1);
+        self.y_pos = self.y_pos.min(last_line);
+        self.current_page = self.current_page.min(self.y_pos);
+        self.page_size = self.text_height();
+        if self.y_pos >= add_safe(self.current_page, self.page_size) {
+            self.current_page = sub_safe(add_safe(self.y_pos, 1), self.page_size.max(1));
+        }
+        let len_chars = text.len_chars();
+        self.start_select_pos = self.start_select_pos.map(|p| p.min(len_chars));
+        self.end_select_pos = self.end_select_pos.map(|p| p.min(len_chars));
+    }
+
     pub fn update(&mut self, change: WindowChange) {
         self.x_pos = change.x_pos;
         self.y_pos = change.y_pos;
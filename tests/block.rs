@@ -0,0 +1,41 @@
+use ri::{
+    app::Mode,
+    block::{self, Cut, Split},
+    buffer::SelectMode,
+};
+
+#[test]
+fn block_columns_with_tabs_and_wide_chars() {
+    assert_eq!(block::columns("a\tb", 4), vec![0, 1, 4, 5]);
+    assert_eq!(block::columns("日本", 8), vec![0, 2, 4]);
+    assert_eq!(
+        block::cut("a\tbc", 2, 5, 4),
+        Cut {
+            start: 1,
+            end: 3,
+            before: 1,
+            after: 0,
+        }
+    );
+    assert_eq!(block::cut_text("a\tbc", 2, 5, 4), "  b");
+    assert_eq!(block::cut_text("日本語", 1, 4, 8), " 本");
+    assert_eq!(
+        block::split("日本", 1, 8),
+        Split {
+            idx: 0,
+            remove: 1,
+            before: 1,
+            after: 1,
+            short: false,
+        }
+    );
+    assert!(block::split("ab", 5, 8).short);
+    assert_eq!(block::fill('日', 5), "日日 ");
+}
+
+#[test]
+fn footer_names_the_selection_kind() {
+    assert_eq!(Mode::Visual.label(SelectMode::Block), "Visual Block");
+    assert_eq!(Mode::Visual.label(SelectMode::Line), "Visual Line");
+    assert_eq!(Mode::Normal.label(SelectMode::Block), "Normal");
+}
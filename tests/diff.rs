@@ -0,0 +1,11 @@
+use ri::diff::diff_lines;
+
+#[test]
+fn diff_changed_line() {
+    let old = vec!["fn main() {", "    old();", "}"];
+    let new = vec!["fn main() {", "    new();", "}"];
+    assert_eq!(
+        diff_lines(&old, &new),
+        vec!["  fn main() {", "-     old();", "+     new();", "  }"]
+    )
+}
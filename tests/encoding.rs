@@ -0,0 +1,62 @@
+use ri::encoding::{decode, encode, read_utf8, Encoding, LineEnding};
+
+#[test]
+fn decode_crlf_with_bom() {
+    let bytes = b"\xEF\xBB\xBFfn main() {\r\n}\r\n";
+    let (text, encoding, bom, line_ending) = decode(bytes);
+    assert_eq!(text, "fn main() {\n}\n");
+    assert_eq!(encoding, Encoding::Utf8);
+    assert!(bom);
+    assert_eq!(line_ending, LineEnding::Crlf);
+    assert_eq!(
+        encode(&text, encoding, bom, line_ending).unwrap(),
+        bytes.to_vec()
+    );
+}
+
+#[test]
+fn invalid_utf8_after_bom_round_trips() {
+    let bytes = b"\xEF\xBB\xBF\xFF\x41";
+    let (text, encoding, bom, line_ending) = decode(bytes);
+    assert_eq!(encoding, Encoding::Latin1);
+    assert!(!bom);
+    assert!(!text.contains('\u{FFFD}'));
+    assert_eq!(
+        encode(&text, encoding, bom, line_ending).unwrap(),
+        bytes.to_vec()
+    );
+}
+
+#[test]
+fn utf16_without_bom_is_written_back_without_one() {
+    let bytes = "hi\nyo\n"
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes())
+        .collect::<Vec<u8>>();
+    let (text, encoding, bom, line_ending) = decode(&bytes);
+    assert_eq!(text, "hi\nyo\n");
+    assert_eq!(encoding, Encoding::Utf16Le);
+    assert!(!bom);
+    assert_eq!(encode(&text, encoding, bom, line_ending).unwrap(), bytes);
+}
+
+#[test]
+fn decode_latin1() {
+    let (text, encoding, _, _) = decode(b"caf\xE9\n");
+    assert_eq!(text, "café\n");
+    assert_eq!(encoding, Encoding::Latin1);
+}
+
+#[test]
+fn read_utf8_converts_line_endings_across_chunks() {
+    // 65536 is 2 mod 14 and 2 mod 3, so chunk edges split both a two-byte character and a
+    // "\r\n" pair.
+    for line in ["héllo wörl\r\n", "a\r\n"] {
+        let bytes = format!("\u{feff}{}", line.repeat(50_000));
+        let (rope, bom, line_ending) = read_utf8(bytes.as_bytes()).unwrap().unwrap();
+        assert!(bom);
+        assert_eq!(line_ending, LineEnding::Crlf);
+        assert_eq!(rope.to_string(), line.replace("\r\n", "\n").repeat(50_000));
+    }
+    assert!(read_utf8(&b"caf\xE9\n"[..]).unwrap().is_none());
+}
@@ -0,0 +1,37 @@
+use ri::file::{backup_path, write_atomic};
+use std::fs;
+use std::path::PathBuf;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ri-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn write_atomic_replaces_contents_and_keeps_a_backup() {
+    let dir = scratch_dir("write-atomic");
+    let path = dir.join("notes.txt");
+    write_atomic(&path, false, |w| w.write_all(b"first\n")).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "first\n");
+
+    write_atomic(&path, true, |w| w.write_all(b"second\n")).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+    assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "first\n");
+
+    let failed = write_atomic(&path, false, |w| {
+        w.write_all(b"partial")?;
+        Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+    });
+    assert!(failed.is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+
+    let mut names = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    names.sort();
+    assert_eq!(names, vec!["notes.txt", "notes.txt~"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
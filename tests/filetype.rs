@@ -0,0 +1,46 @@
+use ri::filetype::{detect, modeline_options};
+use ri::highlight::Highlighting;
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(|l| l.to_owned()).collect()
+}
+
+#[test]
+fn detect_by_name_extension_and_shebang() {
+    assert_eq!(detect(Some("src/main.rs"), &[]), "rust");
+    assert_eq!(detect(Some("Cargo.lock"), &[]), "toml");
+    assert_eq!(detect(Some("Makefile"), &[]), "make");
+    assert_eq!(
+        detect(Some("script"), &lines("#!/usr/bin/env python3\nprint(1)")),
+        "python"
+    );
+    assert_eq!(detect(None, &lines("plain words")), "");
+}
+
+#[test]
+fn modeline_overrides_extension() {
+    let text = lines("x = 1\n# vim: set ft=ruby ts=2 et:");
+    assert_eq!(detect(Some("build.py"), &text), "ruby");
+    assert_eq!(modeline_options(&text), vec!["ft=ruby", "ts=2", "et"]);
+}
+
+#[test]
+fn detected_filetypes_have_a_syntax() {
+    let highlighting = Highlighting::load();
+    for name in [
+        "Cargo.toml",
+        "Cargo.lock",
+        "Makefile",
+        "main.py",
+        "app.js",
+        "run.sh",
+    ] {
+        let filetype = detect(Some(name), &[]);
+        assert_ne!(
+            highlighting.syntax_for(&filetype).name,
+            "Plain Text",
+            "{}",
+            name
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use ri::fold::{indent_folds, marker_folds, Fold, FoldMethod, Folds};
+use ropey::Rope;
+
+#[test]
+fn indent_folds_nest_and_span_blank_lines() {
+    let text = Rope::from_str("a\n    b\n\n    c\n        d\ne\n");
+    assert_eq!(
+        indent_folds(&text, 4),
+        vec![Fold::new(4, 4), Fold::new(1, 4)]
+    );
+}
+
+#[test]
+fn marker_folds_pair_up() {
+    let text = Rope::from_str("// {{{\na\n// {{{\nb\n// }}}\n// }}}\nc\n");
+    assert_eq!(marker_folds(&text), vec![Fold::new(2, 4), Fold::new(0, 5)]);
+}
+
+#[test]
+fn closed_fold_moves_as_one_line() {
+    let text = Rope::from_str("a\nb\nc\nd\ne\n");
+    let mut folds = Folds::default();
+    folds.update(FoldMethod::Manual, &text, 4, None, 0);
+    folds.create(1, 3).unwrap();
+    assert_eq!(folds.closed_ranges(), vec![(1, 3)]);
+    assert_eq!(folds.line_below(0), 1);
+    assert_eq!(folds.line_below(1), 4);
+    assert_eq!(folds.line_above(4), 1);
+    assert_eq!(folds.column_marker(2), '+');
+
+    folds.toggle(2).unwrap();
+    assert_eq!(folds.closed_ranges(), vec![]);
+    assert_eq!(folds.line_below(1), 2);
+    assert!(folds.open(0).is_err());
+}
+
+#[test]
+fn manual_folds_follow_line_edits() {
+    let text = Rope::from_str("a\nb\nc\nd\ne\nf\n");
+    let mut folds = Folds::default();
+    folds.update(FoldMethod::Manual, &text, 4, None, 0);
+    folds.create(2, 3).unwrap();
+
+    folds.insert_line(0);
+    assert_eq!(folds.closed_ranges(), vec![(3, 4)]);
+    folds.insert_line(4);
+    assert_eq!(folds.closed_ranges(), vec![(3, 5)]);
+    folds.remove_line(1);
+    assert_eq!(folds.closed_ranges(), vec![(2, 4)]);
+    folds.remove_line(3);
+    assert_eq!(folds.closed_ranges(), vec![(2, 3)]);
+    folds.remove_line(2);
+    folds.remove_line(2);
+    assert_eq!(folds.closed_ranges(), vec![]);
+}
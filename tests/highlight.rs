@@ -0,0 +1,102 @@
+use ri::highlight::HighlightCache;
+use ri::syntax_tree::input_edit;
+use ropey::Rope;
+use std::sync::Arc;
+use syntect::{highlighting::ThemeSet, parsing::SyntaxSet};
+
+fn rust_cache() -> HighlightCache {
+    let ps = SyntaxSet::load_defaults_newlines();
+    let ts = ThemeSet::load_defaults();
+    let syntax = ps.find_syntax_by_extension("rs").cloned().unwrap();
+    let theme = ts.themes["base16-ocean.dark"].clone();
+    HighlightCache::new(Arc::new(ps), syntax, Arc::new(theme))
+}
+
+fn styles(spans: &[Vec<ri::window::CachedSpan>]) -> Vec<Vec<(String, ratatui::style::Style)>> {
+    spans
+        .iter()
+        .map(|line| line.iter().map(|s| (s.content.clone(), s.style)).collect())
+        .collect()
+}
+
+#[test]
+fn reopening_comment_rehighlights_following_lines() {
+    let mut text = Rope::from_str("fn a() {}\n// x\nfn b() {}\nfn c() {}\n");
+    let mut cache = rust_cache();
+    let mut spans = vec![];
+    cache.highlight(&text, &mut spans, usize::MAX);
+
+    text.remove(10..12);
+    text.insert(10, "/*");
+    cache.invalidate(1);
+    cache.highlight(&text, &mut spans, usize::MAX);
+
+    let mut fresh = rust_cache();
+    let mut expected = vec![];
+    fresh.highlight(&text, &mut expected, usize::MAX);
+    assert_eq!(styles(&spans), styles(&expected));
+}
+
+#[test]
+fn input_edit_covers_changed_span() {
+    let old = Rope::from_str("fn a() {}\nfn b() {}\n");
+    let new = Rope::from_str("fn a() {}\nfn bc() {}\n");
+    let edit = input_edit(&old, &new).unwrap();
+    assert_eq!(
+        (edit.start_byte, edit.old_end_byte, edit.new_end_byte),
+        (14, 14, 15)
+    );
+    assert_eq!(
+        (edit.start_position.row, edit.start_position.column),
+        (1, 4)
+    );
+}
+
+#[test]
+fn input_edit_matches_across_shared_and_fresh_ropes() {
+    let line = "let value = \"é\";\n";
+    let old = Rope::from_str(&line.repeat(5_000));
+    let mut new = old.clone();
+    let at = new.line_to_char(2_500) + 13;
+    new.insert(at, "ü");
+    let fresh = Rope::from_str(&new.to_string());
+    for new in [new, fresh] {
+        let edit = input_edit(&old, &new).unwrap();
+        let start = old.line_to_byte(2_500) + 13;
+        assert_eq!(
+            (edit.start_byte, edit.old_end_byte, edit.new_end_byte),
+            (start, start, start + 2)
+        );
+        assert_eq!(
+            (edit.start_position.row, edit.new_end_position.column),
+            (2_500, 15)
+        );
+    }
+}
+
+#[test]
+fn text_objects_follow_the_syntax_tree() {
+    use ri::syntax_tree::{SyntaxTree, TextObject};
+    let source = "fn a(x: u8, y: u8) {\n    x;\n}\nfn b() {}\n";
+    let text = Rope::from_str(source);
+    let mut tree = SyntaxTree::new("rust").unwrap();
+    tree.update(&text, 1);
+
+    let body = source.find("x;").unwrap();
+    assert_eq!(
+        tree.text_object(body, TextObject::Function, false),
+        Some(0..29)
+    );
+    assert_eq!(
+        tree.text_object(body, TextObject::Function, true),
+        Some(20..28)
+    );
+    let x = source.find("x:").unwrap();
+    assert_eq!(tree.text_object(x, TextObject::Argument, true), Some(5..10));
+    assert_eq!(
+        tree.text_object(x, TextObject::Argument, false),
+        Some(5..12)
+    );
+    assert_eq!(tree.function_start(0, true), Some(30));
+    assert_eq!(tree.function_start(30, false), Some(0));
+}
@@ -0,0 +1,87 @@
+use ratatui::layout::{Direction, Rect};
+use ri::layout::{
+    border_at, equal, neighbour, resize, resize_target, window_at, Edge, WindowLayout,
+};
+use uuid::Uuid;
+
+#[test]
+fn neighbour_prefers_the_window_level_with_the_cursor() {
+    let (left, top_right, bottom_right) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let areas = vec![
+        (left, Rect::new(0, 0, 40, 20)),
+        (top_right, Rect::new(40, 0, 40, 10)),
+        (bottom_right, Rect::new(40, 10, 40, 10)),
+    ];
+    let current = Rect::new(0, 0, 40, 20);
+    assert_eq!(
+        neighbour(&areas, &current, (5, 15), Edge::Right),
+        Some(bottom_right)
+    );
+    assert_eq!(
+        neighbour(&areas, &current, (5, 2), Edge::Right),
+        Some(top_right)
+    );
+    assert_eq!(neighbour(&areas, &current, (5, 2), Edge::Left), None);
+    assert_eq!(
+        neighbour(&areas, &areas[2].1, (45, 12), Edge::Top),
+        Some(top_right)
+    );
+}
+
+#[test]
+fn nested_splits_tile_the_area_and_closing_gives_space_back() {
+    let (left, top_right, bottom_right) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let mut layout = WindowLayout::new(left);
+    assert!(layout.split(left, top_right, Direction::Horizontal));
+    assert!(layout.split(top_right, bottom_right, Direction::Vertical));
+    let area = Rect::new(0, 0, 80, 20);
+    assert_eq!(
+        layout.areas(area),
+        vec![
+            (left, Rect::new(0, 0, 40, 20)),
+            (top_right, Rect::new(40, 0, 40, 10)),
+            (bottom_right, Rect::new(40, 10, 40, 10)),
+        ]
+    );
+    assert!(layout.remove(top_right));
+    assert_eq!(
+        layout.areas(area),
+        vec![
+            (left, Rect::new(0, 0, 40, 20)),
+            (bottom_right, Rect::new(40, 0, 40, 20)),
+        ]
+    );
+}
+
+#[test]
+fn resize_takes_from_the_following_window_first() {
+    let mut sizes = vec![10, 10, 10];
+    resize(&mut sizes, 0, 25, 4);
+    assert_eq!(sizes, vec![22, 4, 4]);
+    resize(&mut sizes, 2, 10, 4);
+    assert_eq!(sizes, vec![16, 4, 10]);
+    assert_eq!(equal(31, 3), vec![11, 10, 10]);
+    assert_eq!(resize_target("+5", 10).ok(), Some(15));
+    assert!(resize_target("five", 10).is_err());
+}
+
+#[test]
+fn dragging_a_header_or_first_column_resizes_the_window_beyond_it() {
+    let (left, top_right, bottom_right) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let areas = vec![
+        (left, Rect::new(0, 0, 40, 20)),
+        (top_right, Rect::new(40, 0, 40, 10)),
+        (bottom_right, Rect::new(40, 10, 40, 10)),
+    ];
+    assert_eq!(window_at(&areas, 45, 12), Some(bottom_right));
+    assert_eq!(
+        border_at(&areas, 50, 10),
+        Some((top_right, Direction::Vertical))
+    );
+    assert_eq!(
+        border_at(&areas, 40, 5),
+        Some((left, Direction::Horizontal))
+    );
+    assert_eq!(border_at(&areas, 5, 0), None);
+    assert_eq!(border_at(&areas, 45, 12), None);
+}
@@ -0,0 +1,25 @@
+use ri::swap;
+use ropey::Rope;
+use std::fs;
+
+#[test]
+fn swap_write_then_recover_round_trip() {
+    let dir = std::env::temp_dir().join(format!("ri-swap-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join("notes.txt").to_string_lossy().to_string();
+    let swap_path = swap::swap_path(&file_path);
+    assert_eq!(swap_path, dir.join(".notes.txt.swp"));
+
+    let text = Rope::from_str("first line\n---\nlast line\n");
+    swap::write(&swap_path, &file_path, &text).unwrap();
+
+    let info = swap::read_info(&swap_path).unwrap();
+    assert_eq!(info.pid, std::process::id());
+    assert_eq!(info.file_path, file_path);
+    assert_eq!(swap::read_contents(&swap_path).unwrap(), text);
+
+    swap::remove(&swap_path);
+    assert!(swap::read_info(&swap_path).is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,18 @@
+use ri::tab::{label, move_target};
+
+#[test]
+fn tab_label_shows_windows_and_changes() {
+    assert_eq!(label("app.rs", false, 1), " app.rs ");
+    assert_eq!(label("app.rs", true, 2), " 2+ app.rs ");
+}
+
+#[test]
+fn tabmove_targets() {
+    assert_eq!(move_target("", 0, 4).ok(), Some(3));
+    assert_eq!(move_target("0", 2, 4).ok(), Some(0));
+    assert_eq!(move_target("2", 0, 4).ok(), Some(1));
+    assert_eq!(move_target("1", 3, 4).ok(), Some(1));
+    assert_eq!(move_target("+1", 1, 4).ok(), Some(2));
+    assert!(move_target("+3", 1, 4).is_err());
+    assert!(move_target("-2", 1, 4).is_err());
+}
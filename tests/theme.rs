@@ -0,0 +1,16 @@
+use ratatui::style::Color;
+use ri::theme::ColorSupport;
+
+#[test]
+fn rgb_falls_back_to_terminal_palette() {
+    assert_eq!(
+        ColorSupport::TrueColor.rgb(10, 20, 30),
+        Color::Rgb(10, 20, 30)
+    );
+    assert_eq!(ColorSupport::Ansi256.rgb(255, 0, 0), Color::Indexed(196));
+    assert_eq!(
+        ColorSupport::Ansi256.rgb(128, 128, 128),
+        Color::Indexed(244)
+    );
+    assert_eq!(ColorSupport::Ansi16.rgb(250, 10, 10), Color::LightRed);
+}
@@ -1,6 +1,11 @@
 use ri::{
-    app::Mode,
-    token::{get_tokens_from_chars, NormalToken, OperatorToken, RangeToken, Token},
+    app::{HistoryKind, Mode},
+    buffer::Scroll,
+    layout::Edge,
+    token::{
+        get_token_from_chars, get_tokens_from_chars, normal_token::WINDOW_PREFIX, CommandToken,
+        MotionToken, NormalToken, OperatorToken, Token, VisualToken,
+    },
 };
 
 #[test]
@@ -21,7 +26,7 @@ fn get_chars_invalid_set() {
 fn get_chars_single_char() {
     let chars = vec!['k'];
     let tokens = get_tokens_from_chars(&Mode::Normal, &chars);
-    assert_eq!(tokens, vec![Token::Normal(NormalToken::Up)])
+    assert_eq!(tokens, vec![Token::Motion(MotionToken::Up)])
 }
 
 #[test]
@@ -32,7 +37,169 @@ fn get_chars_delete_till_eol() {
         tokens,
         vec![
             Token::Operator(OperatorToken::Delete),
-            Token::Normal(NormalToken::Last)
+            Token::Motion(MotionToken::Last)
         ]
     )
 }
+
+#[test]
+fn get_chars_command_line_window() {
+    let chars = vec!['q', ':'];
+    let tokens = get_tokens_from_chars(&Mode::Normal, &chars);
+    assert_eq!(
+        tokens,
+        vec![Token::Normal(NormalToken::CommandLineWindow(
+            HistoryKind::Command
+        ))]
+    )
+}
+
+#[test]
+fn get_chars_search() {
+    let chars = vec!['/'];
+    let tokens = get_tokens_from_chars(&Mode::Normal, &chars);
+    assert_eq!(tokens, vec![Token::Normal(NormalToken::SwitchToSearch)])
+}
+
+#[test]
+fn get_chars_quit_variants() {
+    let quit_all = ":qa".chars().collect::<Vec<char>>();
+    let force_quit = ":q!".chars().collect::<Vec<char>>();
+    assert_eq!(
+        get_token_from_chars(&Mode::Command, &quit_all).ok(),
+        Some(Token::Command(CommandToken::QuitAll))
+    );
+    assert_eq!(
+        get_token_from_chars(&Mode::Command, &force_quit).ok(),
+        Some(Token::Command(CommandToken::ForceQuit))
+    );
+}
+
+#[test]
+fn get_chars_write_append() {
+    let chars = ":w >> notes.txt".chars().collect::<Vec<char>>();
+    assert_eq!(
+        get_token_from_chars(&Mode::Command, &chars).ok(),
+        Some(Token::Command(CommandToken::AppendTo(
+            "notes.txt".to_string()
+        )))
+    );
+}
+
+#[test]
+fn get_chars_colorscheme() {
+    let chars = ":colo Solarized (dark)".chars().collect::<Vec<char>>();
+    assert_eq!(
+        get_token_from_chars(&Mode::Command, &chars).ok(),
+        Some(Token::Command(CommandToken::ColorScheme(Some(
+            "Solarized (dark)".to_string()
+        ))))
+    );
+}
+
+#[test]
+fn get_chars_operator_text_object() {
+    let chars = vec!['d', 'a', 'f'];
+    let tokens = get_tokens_from_chars(&Mode::Normal, &chars);
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Operator(OperatorToken::Delete),
+            Token::Motion(MotionToken::AroundFunction)
+        ]
+    )
+}
+
+#[test]
+fn get_chars_scroll_cursor_center() {
+    let chars = vec!['z', 'z'];
+    let tokens = get_tokens_from_chars(&Mode::Normal, &chars);
+    assert_eq!(
+        tokens,
+        vec![Token::Normal(NormalToken::Scroll(Scroll::CursorCenter))]
+    )
+}
+
+#[test]
+fn get_chars_buffer_commands() {
+    let parse = |command: &str| {
+        get_token_from_chars(&Mode::Command, &command.chars().collect::<Vec<char>>()).ok()
+    };
+    assert_eq!(
+        parse(":b3"),
+        Some(Token::Command(CommandToken::Buffer("3".to_string())))
+    );
+    assert_eq!(
+        parse(":b app"),
+        Some(Token::Command(CommandToken::Buffer("app".to_string())))
+    );
+    assert_eq!(parse(":bn"), Some(Token::Command(CommandToken::NextBuffer)));
+    assert_eq!(
+        parse(":bd! 2"),
+        Some(Token::Command(CommandToken::DeleteBuffer(
+            Some("2".to_string()),
+            true
+        )))
+    );
+    assert_eq!(
+        parse(":bw"),
+        Some(Token::Command(CommandToken::WipeBuffer(None, false)))
+    );
+}
+
+#[test]
+fn get_chars_window_command() {
+    let chars = vec![WINDOW_PREFIX, 'L'];
+    let tokens = get_tokens_from_chars(&Mode::Normal, &chars);
+    assert_eq!(
+        tokens,
+        vec![Token::Normal(NormalToken::MoveWindow(Edge::Right))]
+    )
+}
+
+#[test]
+fn get_chars_vertical_resize() {
+    let chars = ":vert res +5".chars().collect::<Vec<char>>();
+    assert_eq!(
+        get_token_from_chars(&Mode::Command, &chars).ok(),
+        Some(Token::Command(CommandToken::VerticalResize(
+            "+5".to_string()
+        )))
+    );
+}
+
+#[test]
+fn get_chars_tab_commands() {
+    let parse = |s: &str| get_token_from_chars(&Mode::Command, &s.chars().collect()).ok();
+    assert_eq!(
+        parse(":tabnew src/app.rs"),
+        Some(Token::Command(CommandToken::TabNew(Some(
+            "src/app.rs".to_string()
+        ))))
+    );
+    assert_eq!(
+        parse(":tabnew"),
+        Some(Token::Command(CommandToken::TabNew(None)))
+    );
+    assert_eq!(
+        parse(":tabc!"),
+        Some(Token::Command(CommandToken::TabClose(true)))
+    );
+    assert_eq!(
+        parse(":tabm -1"),
+        Some(Token::Command(CommandToken::TabMove("-1".to_string())))
+    );
+    let tokens = get_tokens_from_chars(&Mode::Normal, &"12gt".chars().collect());
+    assert_eq!(tokens, vec![Token::Normal(NormalToken::GoToTab(12))]);
+}
+
+#[test]
+fn get_chars_visual_mode() {
+    let parse = |s: &str| get_tokens_from_chars(&Mode::Visual, &s.chars().collect());
+    assert_eq!(parse("o"), vec![Token::Visual(VisualToken::SwapEnds)]);
+    assert_eq!(parse("rx"), vec![Token::Visual(VisualToken::Replace('x'))]);
+    assert_eq!(parse(">"), vec![Token::Operator(OperatorToken::Indent)]);
+    assert_eq!(parse("w"), vec![Token::Motion(MotionToken::StartWord)]);
+    let tokens = get_tokens_from_chars(&Mode::Normal, &"gv".chars().collect());
+    assert_eq!(tokens, vec![Token::Normal(NormalToken::Reselect)]);
+}